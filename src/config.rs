@@ -2,6 +2,7 @@ use lazy_static::lazy_static;
 use serde::Deserialize;
 use std::collections::HashSet;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -9,6 +10,27 @@ pub struct Config {
     #[serde(default)]
     pub protest_channels: HashSet<u64>,
     pub socket_addr: SocketAddr,
+    /// Where the bot keeps anything it needs to remember between restarts
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+    /// Championship rules which can be picked with `series=<id>`
+    #[serde(default = "default_series_file")]
+    pub series_file: PathBuf,
+    /// Penalties stewards may give with `!decide`
+    #[serde(default = "default_penalties_file")]
+    pub penalties_file: PathBuf,
+}
+
+fn default_data_dir() -> PathBuf {
+    PathBuf::from("data")
+}
+
+fn default_series_file() -> PathBuf {
+    PathBuf::from("series.json")
+}
+
+fn default_penalties_file() -> PathBuf {
+    PathBuf::from("penalties.json")
 }
 
 lazy_static! {
@@ -1,172 +1,2334 @@
+use chrono::Utc;
 use serenity::builder::CreateEmbed;
 use serenity::client::{Client, Context};
 use serenity::framework::standard::{
     help_commands,
     macros::{check, command, group, help, hook},
-    Args, CommandGroup, CommandOptions, CommandResult, HelpOptions, Reason, StandardFramework,
+    Args, CommandGroup, CommandOptions, CommandResult, DispatchError, HelpOptions, Reason,
+    StandardFramework,
 };
-use serenity::model::channel::{ChannelType, GuildChannel, Message};
-use serenity::model::id::{ChannelId, UserId};
+use serenity::http::Http;
+use serenity::model::channel::{ChannelType, GuildChannel, Message, PermissionOverwriteType};
+use serenity::model::id::{ChannelId, RoleId, UserId};
 use serenity::prelude::*;
 use std::collections::HashSet;
+use std::sync::Arc;
 
 use log::{error, info, warn};
 use thiserror::Error;
 
 use crate::config;
+use crate::history;
 use crate::metrics;
-use crate::strategy;
+use crate::pages::PagedEmbed;
+use crate::penalty::{self, Decision};
+use crate::points::{self, PointsEntry};
+use crate::protest::{self, ProtestError, ProtestForm, ProtestStatus};
+use crate::race::{self, RaceStop};
+use crate::session::{self, Session};
+use crate::settings;
+use crate::stewards::StewardRoster;
+use crate::strategy::{self, DiscordText, DisplayOptions, Layout};
+use crate::transcript;
+use crate::vote::{self, VoteError};
 use std::time::Duration;
+use stewart_strat::simulation::{self, SimulationParams};
+use stewart_strat::{
+    format_hms, solver, team, Arguments, Strategy, StrategyError, StrategyInner, StrategyInput,
+};
+
+/// How often running races are checked for stops to call out
+const RACE_TICK: Duration = Duration::from_secs(10);
+
+/// How often to look for votes which have passed their deadline
+const VOTE_TICK: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug)]
+pub enum CommandErr {
+    #[error("Channel creation never reached the cache")]
+    ChannelCreationLost,
+}
+
+#[group]
+#[only_in(guilds)]
+#[prefix("strat")]
+#[commands(
+    strat_calc,
+    strat_sim,
+    strat_solve,
+    strat_team,
+    strat_show,
+    strat_history,
+    strat_rerun,
+    strat_series,
+    strat_defaults
+)]
+#[default_command(strat_calc)]
+struct Strat;
+
+#[command]
+#[aliases("calc")]
+async fn strat_calc(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let input = strategy::arguments_with_display(&args, msg.guild_id)
+        .and_then(|(mut args, display)| Ok((StrategyInput::parse(&mut args)?, display)));
+
+    if let Ok((input, display)) = input {
+        info!("Calculated strategy for user {}", msg.author.name);
+        send_strategies(ctx, msg, input, &display).await?;
+    } else {
+        warn!(
+            "Bad input or help request for strat command, user {}",
+            msg.author.name
+        );
+        msg.channel_id.send_message(ctx, |m| {
+            m.content(format!("{}, try one of the examples below:\n\
+            >>> **Usage:** `!strat <Race Length HH:MM or MMM> <Lap Time> <Fuel per Lap> <Fuel Capacity> [<Mandatory Pits> <Max Stint Length HH:MM>]`\n\
+            **Example 1:** `!strat 2:24 2:18 3.44 120`\n\
+            **Example 2:** `!strat 2:24 2:18 3.44 120 1 0:55`\n\
+            **Changing pace:** add `pace=<Race Time HH:MM or Stint sN>@<Lap Time>/<Fuel per Lap>` as often as needed, e.g. `!strat 24:00 2:18 3.44 120 pace=8:00@2:24/3.6 pace=16:00@2:18/3.44`\n\
+            **Fuel weight:** add `weight=<Seconds per Unit of Fuel>` to slow the car with a full tank, treating the lap time as that on low fuel\n\
+            **Virtual energy:** add `energy=<Energy per Lap>` and `energy_capacity=<Capacity>` (default 100)\n\
+            **Units:** add `unit=<l, gal or kg>`, or convert what to add with `refuel_unit=` and `density=<kg per Litre>` and round it with `refuel_step=`\n\
+            **Rules:** add `series=<Series>` for a championship's rules (see `!strat series`), or `pits=`, `max_stint=`, `window=<Open HH:MM>-<Close HH:MM>` and `refuel=no` yourself\n\
+            **Weather:** add `rain=<Start HH:MM>-<End HH:MM>` for each wet spell and `wet_pace=<Lap Time>` on wets, optionally `slicks_wet=` and `wets_dry=`\n\
+            **Race start:** add `start=<YYYY-MM-DDTHH:MM+HH:MM>` and `pit_time=<Seconds>` to see when each stint and stop happens\n\
+            **Layout:** add `layout=table` for a compact table of each strategy", msg.author.mention()));
+            m
+        }).await?;
+    }
+
+    Ok(())
+}
+
+/// Calculate, remember and reply with the strategies for `input`
+async fn send_strategies(
+    ctx: &Context,
+    msg: &Message,
+    input: StrategyInput,
+    display: &DisplayOptions,
+) -> CommandResult {
+    let strategies = input.calculate();
+    let mut content = if strategies.len() == 1 {
+        "We calculated one strategy for you.".to_string()
+    } else {
+        format!("We calculated {} strategies for you.", strategies.len())
+    };
+
+    // Failing to remember the strategy shouldn't stop us showing it
+    match history::save(msg.author.id, msg.guild_id, input, strategies.clone()) {
+        Ok(id) => content.push_str(&format!(
+            " Use `!strat show {}` to see them again, or `!strat rerun {} key=value` to try a change.",
+            id, id
+        )),
+        Err(e) => error!("Failed to save strategy history: {}", e),
+    }
+
+    strategy_embed(content, &strategies, display)
+        .send(ctx, msg)
+        .await
+}
+
+fn strategy_embed(
+    content: String,
+    strategies: &[Strategy],
+    display: &DisplayOptions,
+) -> PagedEmbed {
+    let mut embed = PagedEmbed::new("Strategy Calculator", content);
+    for s in strategies {
+        match display.layout {
+            Layout::Fields => embed.field(s.title(), &s.as_discord_text(), true),
+            Layout::Table => embed.code_field(s.title(), &s.inner().as_table()),
+        }
+    }
+    embed
+}
+
+async fn strategy_not_found(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.channel_id
+        .say(
+            ctx,
+            format!(
+                "{}, we couldn't find that strategy. Try `!strat history` to see the IDs of your recent ones.",
+                msg.author.mention()
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("show")]
+async fn strat_show(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let words: Vec<&str> = args.raw_quoted().collect();
+    let saved = strategy::split_display(words).map(|(rest, display)| {
+        let saved = rest
+            .first()
+            .and_then(|id| history::get(id, msg.author.id, msg.guild_id));
+        (saved, display)
+    });
+
+    if let Ok((Some(saved), display)) = saved {
+        info!("Showing strategy {} for user {}", saved.id, msg.author.name);
+        let content = format!("Strategies from {}.", saved.summary());
+        strategy_embed(content, &saved.strategies, &display)
+            .send(ctx, msg)
+            .await?;
+    } else {
+        strategy_not_found(ctx, msg).await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("history")]
+async fn strat_history(ctx: &Context, msg: &Message) -> CommandResult {
+    let recent = history::recent(msg.author.id, 10);
+    let content = if recent.is_empty() {
+        format!(
+            "{}, you haven't calculated any strategies yet.",
+            msg.author.mention()
+        )
+    } else {
+        let lines: Vec<String> = recent.iter().map(|saved| saved.summary()).collect();
+        format!(
+            "{}, your recent strategies:\n>>> {}",
+            msg.author.mention(),
+            lines.join("\n")
+        )
+    };
+
+    msg.channel_id.say(ctx, content).await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("rerun")]
+async fn strat_rerun(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let saved = match args
+        .single::<String>()
+        .ok()
+        .and_then(|id| history::get(&id, msg.author.id, msg.guild_id))
+    {
+        Some(saved) => saved,
+        None => return strategy_not_found(ctx, msg).await,
+    };
+
+    let words: Vec<&str> = args.raw_quoted().skip(1).collect();
+    let input = strategy::split_display(words).and_then(|(rest, display)| {
+        let mut input = saved.input.clone();
+        for (key, value) in Arguments::new(rest).options()? {
+            if !input.apply_option(&key, &value)? {
+                return Err(StrategyError::UnknownOption(key));
+            }
+        }
+        input.validate()?;
+        Ok((input, display))
+    });
+
+    match input {
+        Ok((input, display)) => {
+            info!(
+                "Re-running strategy {} for user {}",
+                saved.id, msg.author.name
+            );
+            send_strategies(ctx, msg, input, &display).await?;
+        }
+        Err(e) => {
+            msg.channel_id
+                .say(
+                    ctx,
+                    format!(
+                        "{}, {}. Add `key=value` options after the ID to change the strategy, e.g. `!strat rerun {} pit_time=45`.",
+                        msg.author.mention(),
+                        e,
+                        saved.id
+                    ),
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("sim")]
+async fn strat_sim(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let parsed = SimulationParams::parse(&mut strategy::arguments(&args, msg.guild_id));
+
+    if let Ok((input, params)) = parsed {
+        let ignored = simulation::ignored_options(&input);
+        // Thousands of races take a while, so keep them off the async workers
+        let (outcomes, params) =
+            tokio::task::spawn_blocking(move || (simulation::simulate(&input, &params), params))
+                .await?;
+        info!("Simulated strategies for user {}", msg.author.name);
+        let mut content = format!(
+            "We simulated {} races with a {:.0}% chance of a safety car each hour.",
+            params.runs,
+            params.safety_car_chance * 100.0
+        );
+        if !ignored.is_empty() {
+            content.push_str(&format!(
+                " Laps are run at the average lap time, ignoring {}.",
+                ignored
+                    .iter()
+                    .map(|option| format!("`{}`", option))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        msg.channel_id
+            .send_message(ctx, |m| {
+                m.content(msg.author.mention());
+                m.embed(|e| {
+                    e.title("Safety Car Simulation");
+                    e.description(content);
+                    for o in outcomes {
+                        e.field(o.strategy.title(), o.as_discord_text(), true);
+                    }
+                    e
+                });
+                m
+            })
+            .await?;
+    } else {
+        warn!(
+            "Bad input or help request for strat sim command, user {}",
+            msg.author.name
+        );
+        msg.channel_id.send_message(ctx, |m| {
+            m.content(format!("{}, try one of the examples below:\n\
+            >>> **Usage:** `!strat sim <Race Length HH:MM or MMM> <Lap Time> <Fuel per Lap> <Fuel Capacity> [<Mandatory Pits> <Max Stint Length HH:MM>] [runs=<N up to 50000>] [sc=<Chance per Hour>] [sc_time=<Length MMM>] [sc_pace=<Lap Time Multiplier>] [sc_fuel=<Fuel Multiplier>] [pit_loss=<Seconds>]`\n\
+            **Example 1:** `!strat sim 2:24 2:18 3.44 120`\n\
+            **Example 2:** `!strat sim 2:24 2:18 3.44 120 1 0:55 sc=0.3 sc_time=6 pit_loss=55`", msg.author.mention()));
+            m
+        }).await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("solve")]
+async fn strat_solve(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    if let Ok((input, target_stops)) = solver::parse(&mut strategy::arguments(&args, msg.guild_id))
+    {
+        info!("Solving fuel consumption for user {}", msg.author.name);
+        // Each guess is a full strategy calculation, so keep the search off the async workers
+        let (solution, input) = tokio::task::spawn_blocking(move || {
+            (solver::solve_fuel_per_lap(&input, target_stops), input)
+        })
+        .await?;
+        let content = match solution {
+            Some(solution) => format!(
+                "To finish on {} stops you can use up to **{:.2} {}** per lap, running {} laps on a full tank.",
+                target_stops,
+                solution.fuel_per_lap,
+                input.fuel_units.unit.symbol(),
+                solution.stint_laps
+            ),
+            None => format!(
+                "There's no way to finish on {} stops, the regulations require more than that.",
+                target_stops
+            ),
+        };
+
+        msg.channel_id
+            .send_message(ctx, |m| {
+                m.content(msg.author.mention());
+                m.embed(|e| {
+                    e.title("Strategy Solver");
+                    e.description(content);
+                    e
+                });
+                m
+            })
+            .await?;
+    } else {
+        warn!(
+            "Bad input or help request for strat solve command, user {}",
+            msg.author.name
+        );
+        msg.channel_id.send_message(ctx, |m| {
+            m.content(format!("{}, give the lap time you'll run and we'll find the most fuel per lap you can use on that many stops. Try the example below:\n\
+            >>> **Usage:** `!strat solve <Race Length HH:MM or MMM> <Lap Time> <Fuel Capacity> <Stops> [key=value...]`\n\
+            **Example:** `!strat solve 4:00 2:18 110 2`", msg.author.mention()));
+            m
+        }).await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("team")]
+async fn strat_team(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let plan = strategy::team_arguments(&args, msg.guild_id).and_then(|(mut first, mut second)| {
+        let first = StrategyInput::parse(&mut first)?;
+        let second = StrategyInput::parse(&mut second)?;
+        Ok(team::plan_team(&first, &second))
+    });
+
+    if let Ok(plan) = plan {
+        info!("Planned team strategy for user {}", msg.author.name);
+        let mut embed = PagedEmbed::new("Team Strategy", plan.as_discord_text());
+        for (i, car) in plan.cars.iter().enumerate() {
+            embed.field(
+                format!("Car {}: {}", i + 1, car.title()),
+                &car.as_discord_text(),
+                true,
+            );
+        }
+        embed.send(ctx, msg).await?;
+    } else {
+        warn!(
+            "Bad input or help request for strat team command, user {}",
+            msg.author.name
+        );
+        msg.channel_id.send_message(ctx, |m| {
+            m.content(format!("{}, try the example below:\n\
+            >>> **Usage:** `!strat team <Car 1, as for !strat> vs <Car 2, as for !strat>`\n\
+            **Example:** `!strat team 4:00 2:18 3.25 110 pit_time=40 vs 4:00 2:20 3.3 110 pit_time=40`\n\
+            The second car's stops are moved a few laps where both cars would otherwise need the shared pit box at once.", msg.author.mention()));
+            m
+        }).await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("series")]
+async fn strat_series(ctx: &Context, msg: &Message) -> CommandResult {
+    let profiles: Vec<String> = settings::SERIES
+        .iter()
+        .map(|(id, profile)| {
+            format!(
+                "`series={}` {}: `{}`",
+                id,
+                profile.name,
+                profile.options.join(" ")
+            )
+        })
+        .collect();
+
+    let content = if profiles.is_empty() {
+        format!(
+            "{}, we don't have the rules for any series yet.",
+            msg.author.mention()
+        )
+    } else {
+        format!(
+            "{}, add one of these to a strategy command to use that series' rules:\n>>> {}",
+            msg.author.mention(),
+            profiles.join("\n")
+        )
+    };
+
+    msg.channel_id.say(ctx, content).await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("defaults")]
+#[required_permissions("MANAGE_GUILD")]
+async fn strat_defaults(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let reply = if args.is_empty() {
+        describe_defaults(&settings::guild(Some(guild_id)).strategy_defaults)
+    } else if args.message() == "clear" {
+        settings::GUILDS.update(|guilds| {
+            if let Some(guild) = guilds.get_mut(&guild_id.0) {
+                guild.strategy_defaults.clear();
+            }
+        })?;
+        info!("Cleared strategy defaults for guild {}", guild_id);
+        "Strategy defaults for this server have been cleared.".to_string()
+    } else {
+        match strategy::parse_defaults(&args) {
+            Ok(options) => {
+                let defaults = settings::GUILDS.update(|guilds| {
+                    let defaults = &mut guilds.entry(guild_id.0).or_default().strategy_defaults;
+                    for (key, value) in options {
+                        defaults.retain(|(existing, _)| *existing != key);
+                        defaults.push((key, value));
+                    }
+                    defaults.clone()
+                })?;
+                info!("Updated strategy defaults for guild {}", guild_id);
+                describe_defaults(&defaults)
+            }
+            Err(e) => format!(
+                "{}. A server can set defaults for `{}`, e.g. `!strat defaults unit=gal refuel_step=0.5`, or `!strat defaults clear` to remove them.",
+                e,
+                settings::STRATEGY_DEFAULT_KEYS.join("`, `")
+            ),
+        }
+    };
+
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+fn describe_defaults(defaults: &[(String, String)]) -> String {
+    if defaults.is_empty() {
+        "this server has no strategy defaults.".to_string()
+    } else {
+        let options: Vec<String> = defaults
+            .iter()
+            .map(|(key, value)| format!("`{}={}`", key, value))
+            .collect();
+        format!(
+            "strategy commands on this server start with {}.",
+            options.join(" ")
+        )
+    }
+}
+
+#[group]
+#[only_in(guilds)]
+#[prefix("race")]
+#[commands(
+    race_status,
+    race_start,
+    race_pause,
+    race_resume,
+    race_adjust,
+    race_stop,
+    race_telemetry
+)]
+#[default_command(race_status)]
+struct Race;
+
+#[command]
+#[aliases("start")]
+async fn race_start(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let channel = msg.channel_id.0;
+    if race::RACES.read(|races| races.contains_key(&channel)) {
+        msg.channel_id
+            .say(
+                ctx,
+                format!(
+                    "{}, there's already a race running here. Use `!race stop` to end it first.",
+                    msg.author.mention()
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let (mut arguments, drivers) = strategy::race_arguments(&args, msg.guild_id);
+    let saved = args
+        .current()
+        .and_then(|id| history::get(id, msg.author.id, msg.guild_id));
+    let plan = match saved {
+        Some(saved) => {
+            // A saved calculation may hold several strategies, picked by number after the ID
+            let choice = args
+                .raw_quoted()
+                .filter(|arg| serenity::utils::parse_username(arg).is_none())
+                .nth(1);
+            saved
+                .plan(choice)
+                .map(|strategy| {
+                    (
+                        saved.input.clone(),
+                        strategy.clone(),
+                        Some(saved.id.clone()),
+                    )
+                })
+                .map_err(|e| e.to_string())
+        }
+        None => StrategyInput::parse(&mut arguments)
+            .map(|input| {
+                let strategy = input.calculate().remove(0);
+                (input, strategy, None)
+            })
+            .map_err(|e| e.to_string()),
+    };
+
+    let (input, strategy, id) = match plan {
+        Ok(plan) => plan,
+        Err(e) => {
+            msg.channel_id.send_message(ctx, |m| {
+                m.content(format!("{}, {}. Try one of the examples below:\n\
+                >>> **Usage:** `!race start <Strategy ID and plan number, or arguments as for !strat> [@Driver...]`\n\
+                **Example 1:** `!race start 1a2b 2 @Alice @Bob`\n\
+                **Example 2:** `!race start 2:24 2:18 3.44 120 @Alice @Bob`\n\
+                Drivers take turns in the order given. Use `!race pause` and `!race resume` for red flags, `!race adjust <+/-MM:SS>` when the race runs ahead of or behind the plan, and `!race telemetry` to have a telemetry relay keep the plan up to date.", msg.author.mention(), e));
+                m
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    let mut race = race::Race::new(
+        channel,
+        &input,
+        strategy.inner(),
+        drivers.iter().map(|driver| driver.0).collect(),
+        msg.author.id.0,
+        Utc::now(),
+    );
+    race.strategy_id = id;
+    let reply = format!(
+        "{}, the race clock is running on the {} strategy with {} stops. We'll call out each stop {} laps before it's due.",
+        msg.author.mention(),
+        strategy.title(),
+        race.stops.len(),
+        race::REMINDER_LAPS[0]
+    );
+    race::RACES.update(|races| races.insert(channel, race))?;
+    info!(
+        "Started race clock in channel {} for user {}",
+        channel, msg.author.name
+    );
+
+    msg.channel_id.say(ctx, reply).await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("status")]
+async fn race_status(ctx: &Context, msg: &Message) -> CommandResult {
+    let now = Utc::now();
+    let status = race::RACES.read(|races| {
+        races.get(&msg.channel_id.0).map(|race| {
+            let mut status = format!(
+                "Race time {} of {}",
+                format_hms(Duration::from_secs_f64(race.race_time(now))),
+                format_hms(Duration::from_secs_f64(race.race_secs))
+            );
+            if race.paused_at.is_some() {
+                status.push_str(", paused");
+            }
+            match race.next_stop(now) {
+                Some((i, stop)) => status.push_str(&format!(
+                    ".\nStop {} is due in {} laps, on lap {}: {}.",
+                    i + 1,
+                    race.laps_until(stop, now),
+                    stop.lap,
+                    stop.service
+                )),
+                None => status.push_str(".\nNo more stops, bring it home."),
+            }
+            status
+        })
+    });
+
+    let reply = status.unwrap_or_else(|| {
+        "there's no race running here. Start one with `!race start <Strategy ID> [@Driver...]`."
+            .to_string()
+    });
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("pause")]
+async fn race_pause(ctx: &Context, msg: &Message) -> CommandResult {
+    let now = Utc::now();
+    let paused = race::RACES.update(|races| {
+        races
+            .get_mut(&msg.channel_id.0)
+            .map(|race| (race.pause(now), race.race_time(now)))
+    })?;
+    let reply = match paused {
+        Some((true, race_time)) => format!(
+            "race clock paused at {}. Use `!race resume` when the race restarts.",
+            format_hms(Duration::from_secs_f64(race_time))
+        ),
+        Some((false, _)) => "the race clock is already paused.".to_string(),
+        None => "there's no race running here.".to_string(),
+    };
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("resume")]
+async fn race_resume(ctx: &Context, msg: &Message) -> CommandResult {
+    let now = Utc::now();
+    let resumed = race::RACES.update(|races| {
+        races
+            .get_mut(&msg.channel_id.0)
+            .map(|race| (race.resume(now), race.race_time(now)))
+    })?;
+    let reply = match resumed {
+        Some((true, race_time)) => format!(
+            "race clock running again from {}.",
+            format_hms(Duration::from_secs_f64(race_time))
+        ),
+        Some((false, _)) => "the race clock isn't paused.".to_string(),
+        None => "there's no race running here.".to_string(),
+    };
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("adjust")]
+async fn race_adjust(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let adjustment = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| race::parse_adjustment(&arg));
+    let reply = match adjustment {
+        Some(secs) => {
+            let now = Utc::now();
+            let adjusted = race::RACES.update(|races| {
+                races.get_mut(&msg.channel_id.0).map(|race| {
+                    race.adjust(secs);
+                    race.race_time(now)
+                })
+            })?;
+            match adjusted {
+                Some(race_time) => format!(
+                    "race clock moved to {}.",
+                    format_hms(Duration::from_secs_f64(race_time))
+                ),
+                None => "there's no race running here.".to_string(),
+            }
+        }
+        None => "give the time to move the race clock by as seconds or MM:SS, e.g. `!race adjust -1:30` after a safety car has held the race up.".to_string(),
+    };
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("stop")]
+async fn race_stop(ctx: &Context, msg: &Message) -> CommandResult {
+    let stopped = race::RACES.update(|races| races.remove(&msg.channel_id.0))?;
+    let reply = match stopped {
+        Some(_) => {
+            info!(
+                "Stopped race clock in channel {} for user {}",
+                msg.channel_id, msg.author.name
+            );
+            "race clock stopped."
+        }
+        None => "there's no race running here.",
+    };
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("telemetry")]
+async fn race_telemetry(ctx: &Context, msg: &Message) -> CommandResult {
+    let race = race::RACES.read(|races| races.get(&msg.channel_id.0).cloned());
+    let reply = match race {
+        Some(race) if race.started_by == msg.author.id.0 => {
+            let dm = msg.author.create_dm_channel(ctx).await?;
+            dm.say(
+                ctx,
+                format!(
+                    "Have your telemetry relay POST JSON such as `{{\"lap\": 12, \"fuel\": 54.3, \"lap_time\": 138.2, \"race_time\": 1661.2}}` at the end of each lap to `/telemetry/{}` with the header `Authorization: Bearer {}`. Keep the token to yourself.",
+                    race.channel, race.telemetry_token
+                ),
+            )
+            .await?;
+            "we've sent you the details for sending telemetry."
+        }
+        Some(_) => "only whoever started the race can ask for its telemetry token.",
+        None => "there's no race running here.",
+    };
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+/// Tell a race's channel that telemetry has moved the next stop, with the new plan
+pub async fn send_plan_update(
+    http: &Arc<Http>,
+    channel: ChannelId,
+    was: Option<u32>,
+    plan: &StrategyInner,
+) -> serenity::Result<()> {
+    let headline = match (plan.stops.first(), was) {
+        (Some(stop), Some(was)) => format!("**Box on lap {}**, not lap {}.", stop.lap, was),
+        (Some(stop), None) => format!("**Box on lap {}**, an extra stop is needed.", stop.lap),
+        (None, _) => "**No more stops**, the fuel on board reaches the flag.".to_string(),
+    };
+    channel
+        .send_message(http, |m| {
+            m.embed(|e| {
+                e.title("Plan Updated");
+                e.description(format!(
+                    "{} Telemetry has changed the plan for the rest of the race.\n```\n{}\n```",
+                    headline,
+                    plan.as_table()
+                ));
+                e
+            });
+            m
+        })
+        .await?;
+    Ok(())
+}
+
+/// Call out stops in every running race as they come due, for as long as the bot runs
+pub async fn race_reminders(http: Arc<Http>) {
+    let mut interval = tokio::time::interval(RACE_TICK);
+    loop {
+        interval.tick().await;
+        let now = Utc::now();
+
+        let due: Vec<(race::Race, Vec<race::Reminder>)> = race::RACES.read(|races| {
+            races
+                .values()
+                .map(|race| (race.clone(), race.reminders(now)))
+                .filter(|(race, reminders)| !reminders.is_empty() || race.finished(now))
+                .collect()
+        });
+        if due.is_empty() {
+            continue;
+        }
+
+        // Mark everything as sent before sending, so a failure can't have us repeat ourselves
+        let marked = race::RACES.update(|races| {
+            for (race, _) in &due {
+                if race.finished(now) {
+                    races.remove(&race.channel);
+                } else if let Some(race) = races.get_mut(&race.channel) {
+                    race.remind(now);
+                }
+            }
+        });
+        if let Err(e) = marked {
+            error!("Failed to save race reminders: {}", e);
+            continue;
+        }
+
+        for (race, reminders) in due {
+            let channel = ChannelId(race.channel);
+            for reminder in reminders {
+                let stop = &race.stops[reminder.stop];
+                if let Err(e) = send_race_reminder(&http, channel, reminder, stop).await {
+                    warn!("Failed to send race reminder to {}: {:?}", channel, e);
+                }
+            }
+            if race.finished(now) {
+                info!("Race finished in channel {}", channel);
+                if let Err(e) = channel
+                    .say(&http, "Chequered flag! The race clock has stopped.")
+                    .await
+                {
+                    warn!("Failed to send race finish to {}: {:?}", channel, e);
+                }
+            }
+        }
+    }
+}
+
+async fn send_race_reminder(
+    http: &Arc<Http>,
+    channel: ChannelId,
+    reminder: race::Reminder,
+    stop: &RaceStop,
+) -> serenity::Result<()> {
+    let when = if reminder.laps == 1 {
+        "this lap".to_string()
+    } else {
+        format!("in {} laps", reminder.laps)
+    };
+    let mut content = format!(
+        "**Box {}** for stop {} on lap {}: {}.",
+        when,
+        reminder.stop + 1,
+        stop.lap,
+        stop.service
+    );
+    if let Some(driver) = stop.driver {
+        content.push_str(&format!(" {} takes over.", UserId(driver).mention()));
+    }
+    channel.say(http, content).await?;
+
+    // The driver hears about it once, with time to get ready
+    if let Some(driver) = stop
+        .driver
+        .filter(|_| reminder.laps == race::REMINDER_LAPS[0])
+    {
+        let dm = UserId(driver).create_dm_channel(http.clone()).await?;
+        dm.say(
+            http,
+            format!(
+                "You're in the car after stop {} in {}, {} laps from now. Get ready!",
+                reminder.stop + 1,
+                channel.mention(),
+                reminder.laps
+            ),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[group]
+#[only_in(guilds)]
+#[commands(protest_channel)]
+#[help_available(false)]
+struct Protest;
+
+#[check]
+#[name = "InProtestChannel"]
+async fn protest_channel_check(
+    _ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    _: &CommandOptions,
+) -> Result<(), Reason> {
+    if config::CONFIG.protest_channels.contains(&msg.channel_id.0) {
+        Ok(())
+    } else {
+        warn!("Attempt to call !protest-channel in a non-whitelisted channel");
+        Err(Reason::Log(
+            "This command is only permitted in certain channels.".to_string(),
+        ))
+    }
+}
+
+#[command]
+#[aliases("protest-channel")]
+#[checks(InProtestChannel)]
+async fn protest_channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let new_chan_name = args.single::<String>()?;
+    let source_channel = msg.channel_id.to_channel(ctx).await?.guild();
+
+    // Protests relayed from elsewhere arrive as an embed with nothing else to read
+    let form = match args.remains() {
+        None if !msg.embeds.is_empty() => None,
+        remains => match ProtestForm::parse(remains.unwrap_or_default(), msg.author.id.0) {
+            Ok(form) => Some(form),
+            Err(e) => {
+                warn!("Bad protest form from user {}: {}", msg.author.name, e);
+                msg.reply(
+                    ctx,
+                    format!(
+                        "Sorry, {}. Lodge a protest like this:\n`{}`",
+                        e,
+                        protest::FORM_EXAMPLE
+                    ),
+                )
+                .await?;
+                return Ok(());
+            }
+        },
+    };
+
+    // Whether the protest uses up the driver's late allowance, once its channel exists
+    let mut late_allowance = false;
+    if let Some(guild_id) = msg.guild_id {
+        let late = session::SESSIONS.read(|guilds| {
+            guilds.get(&guild_id.0).and_then(|sessions| {
+                let late = sessions.check_deadline(Utc::now()).err()?;
+                Some((late, sessions.has_late_allowance(msg.author.id.0)))
+            })
+        });
+        if let Some((late, has_allowance)) = late {
+            late_allowance = !is_admin(ctx, msg).await;
+            if late_allowance && !has_allowance {
+                warn!("Late protest from user {}: {}", msg.author.name, late);
+                msg.reply(
+                    ctx,
+                    format!(
+                        "Sorry, {}. An admin may let you lodge it late with `!session allow @You`.",
+                        late
+                    ),
+                )
+                .await?;
+                return Ok(());
+            }
+            info!("Late protest from user {} allowed", msg.author.name);
+        }
+    }
+
+    if let Some(source_channel) = source_channel {
+        let guild_name = source_channel
+            .guild_id
+            .name(ctx)
+            .await
+            .unwrap_or_else(|| "Unknown".to_string());
+        let target_category = source_channel.category_id;
+        let guild_id = source_channel.guild_id;
+        let guild_settings = settings::guild(Some(guild_id));
+        let settings = guild_settings.protests;
+        let roster = guild_settings.stewards;
+
+        let round = session::SESSIONS
+            .read(|guilds| {
+                guilds
+                    .get(&guild_id.0)
+                    .and_then(|sessions| sessions.latest(Utc::now()))
+                    .map(|session| session.name.clone())
+            })
+            .unwrap_or_default();
+        let channels: HashSet<String> = guild_id
+            .channels(ctx)
+            .await?
+            .into_values()
+            .map(|channel| channel.name)
+            .collect();
+        let template = settings
+            .name_template
+            .as_deref()
+            .unwrap_or(protest::DEFAULT_NAME_TEMPLATE);
+        let named = protest::PROTESTS.update(|book| {
+            book.reserve_name(guild_id.0, template, &round, &new_chan_name, &channels)
+        })?;
+        let new_chan_name = match named {
+            Ok(name) => name,
+            Err(e) => {
+                warn!("Couldn't name protest for user {}: {}", msg.author.name, e);
+                msg.reply(ctx, format!("Sorry, {}. Try another name.", e))
+                    .await?;
+                return Ok(());
+            }
+        };
+        info!(
+            "Creating channel \"{}\" for user {}",
+            new_chan_name, msg.author.name
+        );
+        let status = ProtestStatus::Submitted;
+        let accused = form
+            .as_ref()
+            .map(|form| form.against.clone())
+            .unwrap_or_default();
+        // Move the cursor on as the stewards are picked, so protests lodged together take turns
+        let stewards = protest::PROTESTS.update(|book| {
+            let (stewards, cursor) = roster.assign(
+                settings
+                    .stewards_per_protest
+                    .unwrap_or(protest::DEFAULT_STEWARDS_PER_PROTEST),
+                settings.assignment,
+                msg.author.id.0,
+                &accused,
+                &book.steward_load(guild_id.0),
+                book.steward_cursors.get(&guild_id.0).copied().unwrap_or(0),
+            );
+            book.steward_cursors.insert(guild_id.0, cursor);
+            stewards
+        })?;
+        let overwrites = settings.channel_overwrites(
+            guild_id.0,
+            ctx.cache.current_user_id().await.0,
+            msg.author.id.0,
+            &accused,
+            &stewards,
+        );
+        let new_chan = source_channel
+            .guild_id
+            .create_channel(ctx, |c| {
+                let c = c
+                    .name(protest::channel_name(status, &new_chan_name))
+                    .topic(protest::topic(status))
+                    .kind(ChannelType::Text)
+                    .permissions(overwrites);
+                if let Some(category) = target_category {
+                    c.category(category)
+                } else {
+                    c
+                }
+            })
+            .await?;
+        if late_allowance {
+            session::SESSIONS.update(|guilds| {
+                guilds
+                    .entry(guild_id.0)
+                    .or_default()
+                    .take_late_allowance(msg.author.id.0)
+            })?;
+        }
+
+        let mut protest = protest::Protest::new(
+            source_channel.guild_id.0,
+            new_chan.id.0,
+            new_chan_name,
+            msg.author.id.0,
+            accused,
+            Utc::now(),
+        );
+        protest.form = form;
+        protest.stewards = stewards;
+        protest::PROTESTS.update(|book| book.protests.insert(new_chan.id.0, protest.clone()))?;
+
+        info!("Sending intro message to channel");
+        // Serenity only support setting one embed on an outbound message so we just take the
+        // first from that which triggered us
+        let embed: CreateEmbed = match &protest.form {
+            Some(form) => protest_embed(&protest, form),
+            None => msg.embeds[0].clone().into(),
+        };
+
+        // It's possible that the new channel isn't available yet so we may need to wait for it
+        // to appear in the cache.
+        let concrete = wait_for_channel(ctx, &new_chan).await?;
+        let intro_message = concrete
+            .send_message(ctx, |m| {
+                let mut intro = vec![];
+                if protest.form.is_some() {
+                    intro.push(format!(
+                        "Protest by <@{}> against {}",
+                        protest.protester,
+                        mentions(&protest.accused)
+                    ));
+                }
+                if !protest.stewards.is_empty() {
+                    intro.push(format!("Stewards: {}", mentions(&protest.stewards)));
+                }
+                if !intro.is_empty() {
+                    m.content(intro.join("\n"));
+                }
+                m.set_embed(embed)
+            })
+            .await?;
+
+        info!("Created intro message: {:?}", intro_message);
+        info!("Attempting to pin intro message.");
+        intro_message.pin(ctx).await?;
+
+        metrics::PROTEST_CHANNELS_CREATED
+            .with_label_values(&[&msg.author.name, &guild_name])
+            .inc();
+    } else {
+        error!("Received a create-channel message from a non-guild context");
+    }
+
+    Ok(())
+}
+
+#[group]
+#[only_in(guilds)]
+#[prefix("vote")]
+#[commands(vote_open, vote_close, vote_status)]
+#[default_command(vote_cast)]
+#[help_available(false)]
+struct Vote;
+
+#[group]
+#[only_in(guilds)]
+#[prefix("stewards")]
+#[commands(stewards_add, stewards_remove, stewards_team)]
+#[default_command(stewards_list)]
+#[help_available(false)]
+struct Stewards;
+
+#[command]
+#[aliases("list")]
+async fn stewards_list(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let roster = settings::guild(Some(guild_id)).stewards;
+    let load = protest::PROTESTS.read(|book| book.steward_load(guild_id.0));
+    let reply = if roster.stewards.is_empty() {
+        "there are no stewards on the roster, add them with `!stewards add @Steward`.".to_string()
+    } else {
+        let stewards: Vec<String> = roster
+            .stewards
+            .iter()
+            .map(|steward| {
+                let team = roster
+                    .teams
+                    .get(steward)
+                    .map(|team| format!(", {}", team))
+                    .unwrap_or_default();
+                format!(
+                    "<@{}>{}: {} open protests",
+                    steward,
+                    team,
+                    load.get(steward).copied().unwrap_or(0)
+                )
+            })
+            .collect();
+        format!(
+            "protests are assigned to these stewards:\n>>> {}",
+            stewards.join("\n")
+        )
+    };
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("add")]
+#[required_permissions("MANAGE_GUILD")]
+async fn stewards_add(ctx: &Context, msg: &Message) -> CommandResult {
+    update_roster(ctx, msg, |roster, mentioned| {
+        for &steward in mentioned {
+            if !roster.stewards.contains(&steward) {
+                roster.stewards.push(steward);
+            }
+        }
+        format!("{} added to the roster.", mentions(mentioned))
+    })
+    .await
+}
+
+#[command]
+#[aliases("remove")]
+#[required_permissions("MANAGE_GUILD")]
+async fn stewards_remove(ctx: &Context, msg: &Message) -> CommandResult {
+    update_roster(ctx, msg, |roster, mentioned| {
+        roster
+            .stewards
+            .retain(|steward| !mentioned.contains(steward));
+        format!("{} taken off the roster.", mentions(mentioned))
+    })
+    .await
+}
+
+#[command]
+#[aliases("team")]
+#[required_permissions("MANAGE_GUILD")]
+async fn stewards_team(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let team = args.single_quoted::<String>().unwrap_or_default();
+    if team.is_empty() || serenity::utils::parse_username(&team).is_some() {
+        msg.channel_id
+            .say(
+                ctx,
+                format!(
+                    "{}, give the team and then mention its drivers and stewards, e.g. `!stewards team \"Apex Racing\" @Driver @Steward`, or `none` to take them off their team.",
+                    msg.author.mention()
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+    update_roster(ctx, msg, |roster, mentioned| {
+        for &id in mentioned {
+            if team == "none" {
+                roster.teams.remove(&id);
+            } else {
+                roster.teams.insert(id, team.clone());
+            }
+        }
+        if team == "none" {
+            format!("{} are no longer on a team.", mentions(mentioned))
+        } else {
+            format!("{} are on team {}.", mentions(mentioned), team)
+        }
+    })
+    .await
+}
+
+/// Change the roster for everyone mentioned in `msg`
+async fn update_roster(
+    ctx: &Context,
+    msg: &Message,
+    change: impl FnOnce(&mut StewardRoster, &[u64]) -> String,
+) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let mentioned: Vec<u64> = msg.mentions.iter().map(|user| user.id.0).collect();
+    let reply = if mentioned.is_empty() {
+        "mention who you mean.".to_string()
+    } else {
+        let reply = settings::GUILDS.update(|guilds| {
+            change(
+                &mut guilds.entry(guild_id.0).or_default().stewards,
+                &mentioned,
+            )
+        })?;
+        info!("Updated steward roster for guild {}", guild_id);
+        reply
+    };
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+/// Whether the author of `msg` may manage the server
+async fn is_admin(ctx: &Context, msg: &Message) -> bool {
+    match msg.member(ctx).await {
+        Ok(member) => member
+            .permissions(ctx)
+            .await
+            .map(|permissions| permissions.manage_guild())
+            .unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+#[group]
+#[only_in(guilds)]
+#[prefix("session")]
+#[commands(session_add, session_remove, session_allow)]
+#[default_command(session_list)]
+#[help_available(false)]
+struct Sessions;
+
+#[command]
+#[aliases("add")]
+#[required_permissions("MANAGE_GUILD")]
+async fn session_add(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let args: Vec<String> = args
+        .raw_quoted()
+        .map(|arg| arg.trim_matches('"').to_string())
+        .collect();
+    let window = args.iter().find_map(|arg| arg.strip_prefix("window="));
+    let rest: Vec<&String> = args
+        .iter()
+        .filter(|arg| !arg.starts_with("window="))
+        .collect();
+
+    let reply = match rest.as_slice() {
+        [name, ended] => {
+            let added = match Session::parse(name, ended, window, Utc::now()) {
+                Ok(session) => {
+                    let deadline = session.deadline();
+                    session::SESSIONS
+                        .update(|guilds| guilds.entry(guild_id.0).or_default().add(session))?
+                        .map(|_| deadline)
+                }
+                Err(e) => Err(e),
+            };
+            match added {
+                Ok(deadline) => {
+                    info!("Added session {} for guild {}", name, guild_id);
+                    format!(
+                        "protests for **{}** must be lodged by {}.",
+                        name,
+                        deadline.format("%Y-%m-%d %H:%M UTC")
+                    )
+                }
+                Err(e) => format!("{}.", e),
+            }
+        }
+        _ => "give the session's name and when it ends in UTC, e.g. `!session add round-3 \"2021-03-14 20:00\" window=2h`.".to_string(),
+    };
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("remove")]
+#[required_permissions("MANAGE_GUILD")]
+async fn session_remove(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let name = args.rest().trim().trim_matches('"');
+    let removed =
+        session::SESSIONS.update(|guilds| guilds.entry(guild_id.0).or_default().remove(name))?;
+    let reply = match removed {
+        Ok(session) => format!("session **{}** has been removed.", session.name),
+        Err(e) => format!("{}.", e),
+    };
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("allow")]
+#[required_permissions("MANAGE_GUILD")]
+async fn session_allow(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let reply = match args.current().and_then(serenity::utils::parse_username) {
+        Some(driver) => {
+            session::SESSIONS.update(|guilds| {
+                let sessions = guilds.entry(guild_id.0).or_default();
+                if !sessions.late_allowed.contains(&driver) {
+                    sessions.late_allowed.push(driver);
+                }
+            })?;
+            info!(
+                "User {} allowed a late protest in guild {}",
+                driver, guild_id
+            );
+            format!("<@{}> may lodge one protest after the deadline.", driver)
+        }
+        None => "mention the driver, e.g. `!session allow @Driver`.".to_string(),
+    };
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("list")]
+async fn session_list(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let sessions: Vec<String> = session::SESSIONS.read(|guilds| {
+        guilds
+            .get(&guild_id.0)
+            .map(|sessions| {
+                sessions
+                    .sessions
+                    .iter()
+                    .map(|session| {
+                        format!(
+                            "**{}** ends {}, protests by {}",
+                            session.name,
+                            session.ended.format("%Y-%m-%d %H:%M UTC"),
+                            session.deadline().format("%Y-%m-%d %H:%M UTC")
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    });
+    let reply = if sessions.is_empty() {
+        "no race sessions have been set, so protests may be lodged at any time.".to_string()
+    } else {
+        format!(
+            "protests must be lodged in time for the last session to end:\n>>> {}",
+            sessions.join("\n")
+        )
+    };
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+/// The protest heard in the channel of `msg`, telling the author if there isn't one
+async fn protest_or_reply(ctx: &Context, msg: &Message) -> CommandResult<Option<protest::Protest>> {
+    let protest = protest::get(msg.channel_id.0);
+    if protest.is_none() {
+        msg.channel_id
+            .say(
+                ctx,
+                format!(
+                    "{}, this channel isn't for a protest we know about.",
+                    msg.author.mention()
+                ),
+            )
+            .await?;
+    }
+    Ok(protest)
+}
+
+#[command]
+#[aliases("open")]
+#[checks(Steward)]
+async fn vote_open(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let protest = match protest_or_reply(ctx, msg).await? {
+        Some(protest) => protest,
+        None => return Ok(()),
+    };
+    let options: Vec<String> = args
+        .raw_quoted()
+        .map(|arg| arg.trim_matches('"').to_string())
+        .collect();
+    let opened = protest::update(protest.channel, |protest| {
+        if protest.open_vote().is_some() {
+            return Err(VoteError::AlreadyOpen);
+        }
+        let vote = vote::Vote::open(&options, msg.author.id.0, Utc::now())?;
+        let reply = vote.describe();
+        protest.votes.push(vote);
+        Ok(reply)
+    })?
+    .unwrap_or(Err(VoteError::NotOpen));
+    let reply = match opened {
+        Ok(reply) => {
+            info!("Vote opened in protest channel {}", msg.channel_id);
+            reply
+        }
+        Err(e) => format!(
+            "{}, {}. Open a vote like this: `!vote open penalty warning no-action quorum=3 deadline=12h hidden`",
+            msg.author.mention(),
+            e
+        ),
+    };
+    msg.channel_id.say(ctx, reply).await?;
+    Ok(())
+}
 
-#[derive(Error, Debug)]
-pub enum CommandErr {
-    #[error("Invalid argument parsed for command")]
-    InvalidCommandArgument,
-    #[error("Channel creation never reached the cache")]
-    ChannelCreationLost,
+#[command]
+#[checks(Steward)]
+async fn vote_cast(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let protest = match protest_or_reply(ctx, msg).await? {
+        Some(protest) => protest,
+        None => return Ok(()),
+    };
+    let choice = args.rest().trim().trim_matches('"');
+    // Cast the ballot on the vote as it's kept, so ballots cast together all count
+    let cast = protest::update(protest.channel, |protest| match protest.open_vote() {
+        Some(vote) => {
+            let hidden = vote.hidden;
+            vote.cast(msg.author.id.0, choice, Utc::now())
+                .map(|option| (option.to_string(), hidden))
+        }
+        None => Err(VoteError::NotOpen),
+    })?
+    .unwrap_or(Err(VoteError::NotOpen));
+
+    match cast {
+        Ok((option, hidden)) => {
+            if hidden {
+                // Take the vote out of the channel so nobody else can see it
+                if let Err(e) = msg.delete(ctx).await {
+                    warn!(
+                        "Failed to remove hidden vote in {}: {:?}",
+                        msg.channel_id, e
+                    );
+                }
+                let told = msg
+                    .author
+                    .direct_message(ctx, |m| {
+                        m.content(format!("Your vote for `{}` has been counted.", option))
+                    })
+                    .await;
+                if told.is_err() {
+                    msg.channel_id
+                        .say(
+                            ctx,
+                            format!("{}, your vote has been counted.", msg.author.mention()),
+                        )
+                        .await?;
+                }
+            } else {
+                msg.channel_id
+                    .say(
+                        ctx,
+                        format!("{} voted for `{}`.", msg.author.mention(), option),
+                    )
+                    .await?;
+            }
+        }
+        Err(e) => {
+            msg.channel_id
+                .say(ctx, format!("{}, {}.", msg.author.mention(), e))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[aliases("close")]
+#[checks(Steward)]
+async fn vote_close(ctx: &Context, msg: &Message) -> CommandResult {
+    let protest = match protest_or_reply(ctx, msg).await? {
+        Some(protest) => protest,
+        None => return Ok(()),
+    };
+    let closed = protest::update(protest.channel, |protest| {
+        protest.open_vote().map(|vote| {
+            vote.close(Some(msg.author.id.0), Utc::now());
+            vote.describe()
+        })
+    })?
+    .flatten();
+    let reply = match closed {
+        Some(reply) => {
+            info!("Vote closed in protest channel {}", msg.channel_id);
+            reply
+        }
+        None => format!("{}, {}.", msg.author.mention(), VoteError::NotOpen),
+    };
+    msg.channel_id.say(ctx, reply).await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("status")]
+async fn vote_status(ctx: &Context, msg: &Message) -> CommandResult {
+    let protest = match protest_or_reply(ctx, msg).await? {
+        Some(protest) => protest,
+        None => return Ok(()),
+    };
+    let reply = match protest.votes.last() {
+        Some(vote) => vote.describe(),
+        None => format!(
+            "{}, the stewards haven't held a vote on this protest.",
+            msg.author.mention()
+        ),
+    };
+    msg.channel_id.say(ctx, reply).await?;
+    Ok(())
+}
+
+/// Close votes once they pass their deadline, announcing the outcome in the protest channel
+pub async fn vote_deadlines(http: Arc<Http>) {
+    let mut interval = tokio::time::interval(VOTE_TICK);
+    loop {
+        interval.tick().await;
+        let now = Utc::now();
+
+        let closed = protest::PROTESTS.update(|book| {
+            let mut closed = vec![];
+            for (&channel, protest) in book.protests.iter_mut() {
+                if let Some(vote) = protest.open_vote().filter(|vote| vote.overdue(now)) {
+                    vote.close(None, now);
+                    closed.push((channel, vote.describe()));
+                }
+            }
+            closed
+        });
+        let closed = match closed {
+            Ok(closed) => closed,
+            Err(e) => {
+                error!("Failed to save closed votes: {}", e);
+                continue;
+            }
+        };
+
+        for (channel, outcome) in closed {
+            info!("Vote reached its deadline in protest channel {}", channel);
+            if let Err(e) = ChannelId(channel).say(&http, outcome).await {
+                warn!("Failed to announce vote outcome in {}: {:?}", channel, e);
+            }
+        }
+    }
+}
+
+/// Bring the name and topic of a protest's channel up to date with its status
+async fn rename_protest_channel(ctx: &Context, protest: &protest::Protest) {
+    // Discord only lets a channel be renamed a couple of times every ten minutes, so the status
+    // has been saved whether or not this works
+    let renamed = ChannelId(protest.channel)
+        .edit(ctx, |c| {
+            c.name(protest.channel_name()).topic(protest.topic())
+        })
+        .await;
+    if let Err(e) = renamed {
+        warn!(
+            "Failed to rename protest channel {}: {:?}",
+            protest.channel, e
+        );
+    }
 }
 
 #[group]
 #[only_in(guilds)]
-#[prefix("strat")]
-#[commands(strat_calc)]
-#[default_command(strat_calc)]
-struct Strat;
+#[commands(decide, penalties)]
+#[help_available(false)]
+struct Decisions;
 
 #[command]
-#[aliases("calc")]
-async fn strat_calc(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let strategies = strategy::Strategy::from_discord_args(&mut args);
+#[checks(Steward)]
+async fn decide(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let protest = match protest_or_reply(ctx, msg).await? {
+        Some(protest) => protest,
+        None => return Ok(()),
+    };
 
-    if let Ok(strategies) = strategies {
-        info!("Calculated strategy for user {}", msg.author.name);
-        let content = if strategies.len() == 1 {
-            "We calculated one strategy for you.".to_string()
-        } else {
-            format!("We calculated {} strategies for you.", strategies.len())
+    let penalty = args.single::<String>().ok().and_then(|id| {
+        penalty::PENALTIES
+            .get(&id)
+            .map(|penalty| (id.to_lowercase(), penalty))
+    });
+    let driver = args
+        .single::<String>()
+        .ok()
+        .and_then(|mention| serenity::utils::parse_username(&mention));
+    let reason = args.rest().trim();
+    let (penalty_id, penalty, driver) = match (penalty, driver) {
+        (Some((id, penalty)), Some(driver)) if !reason.is_empty() => (id, penalty, driver),
+        _ => {
+            msg.channel_id
+                .say(
+                    ctx,
+                    format!(
+                        "{}, give the penalty, the driver and the reason, e.g. `!decide 5s @Driver Causing a collision at turn 1`. The penalties are {}.",
+                        msg.author.mention(),
+                        penalty_ids()
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let decision = Decision {
+        penalty: penalty_id,
+        name: penalty.name.clone(),
+        kind: penalty.kind.clone(),
+        points: penalty.points,
+        driver,
+        reason: reason.to_string(),
+        by: msg.author.id.0,
+        at: Utc::now(),
+    };
+    let updated = protest::update(protest.channel, |protest| {
+        protest.decisions.push(decision.clone());
+        let decided = protest.status.can_move_to(ProtestStatus::Decided);
+        if decided {
+            protest.set_status(ProtestStatus::Decided, msg.author.id.0, decision.at)?;
+        }
+        Ok::<_, ProtestError>((decided, protest.clone()))
+    })?;
+    let (decided, protest) = match updated {
+        Some(updated) => updated?,
+        None => return Ok(()),
+    };
+    info!(
+        "Decision of {} against user {} in protest channel {}",
+        decision.penalty, driver, msg.channel_id
+    );
+    if decided {
+        rename_protest_channel(ctx, &protest).await;
+    }
+
+    let settings = settings::guild(msg.guild_id).protests;
+    let ban = if decision.points > 0 {
+        let entry = PointsEntry {
+            driver,
+            points: decision.points,
+            penalty: decision.name.clone(),
+            reason: decision.reason.clone(),
+            protest: protest.channel,
+            at: decision.at,
+            cleared: false,
         };
+        points::POINTS.update(|ledgers| {
+            ledgers.entry(protest.guild).or_default().add(
+                entry,
+                settings.points_expiry,
+                settings.ban_points,
+                decision.at,
+            )
+        })?
+    } else {
+        None
+    };
+
+    let published = settings.decisions_channel;
+    for channel in std::iter::once(msg.channel_id).chain(published.map(ChannelId)) {
+        channel
+            .send_message(ctx, |m| m.set_embed(decision_embed(&protest, &decision)))
+            .await?;
+    }
 
+    if let Some(ban) = ban {
+        info!("User {} banned with {} points", ban.driver, ban.points);
+        let steward = settings
+            .steward_role
+            .map(|role| RoleId(role).mention().to_string())
+            .unwrap_or_else(|| "Stewards".to_string());
         msg.channel_id
+            .say(
+                ctx,
+                format!(
+                    "{}, <@{}> has reached {} penalty points and earned a race ban. Their points have been wiped.",
+                    steward, ban.driver, ban.points
+                ),
+            )
+            .await?;
+        published
+            .map(ChannelId)
+            .unwrap_or(msg.channel_id)
             .send_message(ctx, |m| {
-                m.content(msg.author.mention());
                 m.embed(|e| {
-                    e.title("Strategy Calculator");
-                    e.description(content);
-                    for s in strategies {
-                        e.field(s.discord_title(), s.as_discord_text(), true);
-                    }
+                    e.title("Race Ban");
+                    e.description(format!(
+                        "<@{}> will miss the next race for reaching {} penalty points.",
+                        ban.driver, ban.points
+                    ));
+                    e.timestamp(&ban.at);
                     e
-                });
-                m
+                })
             })
             .await?;
-    } else {
-        warn!(
-            "Bad input or help request for strat command, user {}",
-            msg.author.name
-        );
-        msg.channel_id.send_message(ctx, |m| {
-            m.content(format!("{}, try one of the examples below:\n\
-            >>> **Usage:** `!strat <Race Length HH:MM or MMM> <Lap Time> <Fuel per Lap> <Fuel Capacity> [<Mandatory Pits> <Max Stint Length HH:MM>]`\n\
-            **Example 1:** `!strat 2:24 2:18 3.44 120`\n\
-            **Example 2:** `!strat 2:24 2:18 3.44 120 1 0:55`", msg.author.mention()));
-            m
-        }).await?;
     }
-
     Ok(())
 }
 
 #[group]
 #[only_in(guilds)]
-#[commands(protest_channel)]
+#[prefix("points")]
+#[commands(points_event)]
+#[default_command(points_show)]
 #[help_available(false)]
-struct Protest;
+struct Points;
+
+#[command]
+async fn points_show(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let driver = match args.current().and_then(serenity::utils::parse_username) {
+        Some(driver) => driver,
+        None => {
+            msg.channel_id
+                .say(
+                    ctx,
+                    format!(
+                        "{}, mention the driver, e.g. `!points @Driver`.",
+                        msg.author.mention()
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let expiry = settings::guild(Some(guild_id)).protests.points_expiry;
+    let now = Utc::now();
+    let reply = points::POINTS.read(|ledgers| {
+        let ledger = match ledgers.get(&guild_id.0) {
+            Some(ledger) => ledger,
+            None => return format!("<@{}> has no penalty points.", driver),
+        };
+        let mut reply = format!(
+            "<@{}> has **{}** penalty points.",
+            driver,
+            ledger.active_points(driver, expiry, now)
+        );
+        for entry in ledger.history(driver) {
+            let state = if entry.cleared {
+                " (wiped by a ban)"
+            } else if !ledger.is_active(entry, expiry, now) {
+                " (expired)"
+            } else {
+                ""
+            };
+            reply.push_str(&format!(
+                "\n{}: {} points, {} for {} in <#{}>{}",
+                entry.at.format("%Y-%m-%d"),
+                entry.points,
+                entry.penalty,
+                entry.reason,
+                entry.protest,
+                state
+            ));
+        }
+        for ban in ledger.bans.iter().filter(|ban| ban.driver == driver) {
+            reply.push_str(&format!(
+                "\n{}: race ban at {} points",
+                ban.at.format("%Y-%m-%d"),
+                ban.points
+            ));
+        }
+        reply
+    });
+
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("event")]
+#[checks(Steward)]
+async fn points_event(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let held = points::POINTS.update(|ledgers| {
+        let ledger = ledgers.entry(guild_id.0).or_default();
+        ledger.events.push(Utc::now());
+        ledger.events.len()
+    })?;
+    info!("Event {} recorded for guild {}", held, guild_id);
+    msg.channel_id
+        .say(
+            ctx,
+            format!(
+                "{}, that's event {} done. Points which expire after a number of events count it.",
+                msg.author.mention(),
+                held
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+#[command]
+async fn penalties(ctx: &Context, msg: &Message) -> CommandResult {
+    let penalties: Vec<String> = penalty::PENALTIES
+        .iter()
+        .map(|(id, penalty)| match penalty.points {
+            0 => format!("`{}` {}", id, penalty.name),
+            points => format!("`{}` {}, {} points", id, penalty.name, points),
+        })
+        .collect();
+    msg.channel_id
+        .say(
+            ctx,
+            format!(
+                "{}, stewards may give these penalties with `!decide <penalty> @Driver <reason>`:\n>>> {}",
+                msg.author.mention(),
+                penalties.join("\n")
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+fn penalty_ids() -> String {
+    penalty::PENALTIES
+        .iter()
+        .map(|(id, _)| format!("`{}`", id))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A decision as it's published
+fn decision_embed(protest: &protest::Protest, decision: &Decision) -> CreateEmbed {
+    let mut e = CreateEmbed::default();
+    e.title(format!("Stewards' Decision: {}", protest.name));
+    e.description(&decision.reason);
+    e.field("Driver", format!("<@{}>", decision.driver), true);
+    e.field("Penalty", &decision.name, true);
+    if decision.points > 0 {
+        e.field("Points", decision.points, true);
+    }
+    e.field("Protest by", format!("<@{}>", protest.protester), true);
+    if let Some(form) = &protest.form {
+        e.field("Lap", form.lap, true);
+    }
+    e.field("Stewards", format!("<@{}>", decision.by), true);
+    e.timestamp(&decision.at);
+    e
+}
+
+/// The details of a protest as they're pinned in its channel
+fn protest_embed(protest: &protest::Protest, form: &ProtestForm) -> CreateEmbed {
+    let mut e = CreateEmbed::default();
+    e.title(format!("Protest: {}", protest.name));
+    e.description(&form.description);
+    e.field("Protester", format!("<@{}>", protest.protester), true);
+    e.field("Against", mentions(&form.against), true);
+    if !protest.stewards.is_empty() {
+        e.field("Stewards", mentions(&protest.stewards), true);
+    }
+    e.field("Lap", form.lap, true);
+    if let Some(turn) = &form.turn {
+        e.field("Turn", turn, true);
+    }
+    if let Some(car) = &form.car {
+        e.field("Car", car, true);
+    }
+    if let Some(video) = &form.video {
+        e.field("Video", video, false);
+        e.url(video);
+    }
+    e.timestamp(&protest.created);
+    e
+}
+
+fn mentions(users: &[u64]) -> String {
+    users
+        .iter()
+        .map(|id| format!("<@{}>", id))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[group]
+#[only_in(guilds)]
+#[prefix("protest")]
+#[commands(protest_status, protest_close, protest_config, protest_reassign)]
+struct Protests;
+
+#[command]
+#[aliases("close")]
+#[checks(Steward)]
+async fn protest_close(ctx: &Context, msg: &Message) -> CommandResult {
+    // Close the protest as it's kept, keeping a copy to write the transcript from
+    let closed = protest::update(msg.channel_id.0, |protest| {
+        if protest.status != ProtestStatus::Closed {
+            protest.set_status(ProtestStatus::Closed, msg.author.id.0, Utc::now())?;
+        }
+        Ok::<_, ProtestError>(protest.clone())
+    })?;
+    let protest = match closed {
+        Some(protest) => protest?,
+        None => {
+            msg.channel_id
+                .say(
+                    ctx,
+                    format!(
+                        "{}, this channel isn't for a protest we know about.",
+                        msg.author.mention()
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+    msg.channel_id
+        .say(ctx, "Closing this protest and writing up the transcript.")
+        .await?;
+
+    let entries = channel_history(ctx, msg.channel_id).await?;
+    let text = transcript::markdown(&protest, &entries);
+    // Keep our own copy before anything is deleted, so the record survives whatever Discord does
+    let path = transcript::save(&protest, &text)?;
+    info!(
+        "Saved transcript of protest in channel {} to {}",
+        msg.channel_id,
+        path.display()
+    );
+
+    let settings = settings::guild(msg.guild_id).protests;
+    if let Some(archive) = settings.archive_channel {
+        let file_name = transcript::file_name(&protest);
+        ChannelId(archive)
+            .send_files(ctx, vec![(text.as_bytes(), file_name.as_str())], |m| {
+                m.content(format!(
+                    "Transcript of protest **{}**, closed by {}.",
+                    protest.name,
+                    msg.author.mention()
+                ))
+            })
+            .await?;
+    }
+
+    if let Some(category) = settings.archive_category {
+        msg.channel_id
+            .edit(ctx, |c| {
+                c.name(protest.channel_name())
+                    .topic(protest.topic())
+                    .category(ChannelId(category))
+            })
+            .await?;
+        msg.channel_id
+            .say(ctx, "This protest is closed and has been archived.")
+            .await?;
+        info!("Archived protest channel {}", msg.channel_id);
+    } else {
+        msg.channel_id.delete(ctx).await?;
+        info!("Deleted protest channel {}", msg.channel_id);
+    }
+    Ok(())
+}
+
+/// Every message in `channel`, oldest first, ready for a transcript
+async fn channel_history(
+    ctx: &Context,
+    channel: ChannelId,
+) -> Result<Vec<transcript::Entry>, SerenityError> {
+    let mut messages = vec![];
+    let mut before = None;
+    loop {
+        let page = channel
+            .messages(ctx, |r| {
+                if let Some(id) = before {
+                    r.before(id);
+                }
+                r.limit(100)
+            })
+            .await?;
+        // Pages come newest first
+        before = page.last().map(|m| m.id);
+        let done = page.len() < 100;
+        messages.extend(page);
+        if done {
+            break;
+        }
+    }
+
+    Ok(messages
+        .into_iter()
+        .rev()
+        .map(|m| transcript::Entry {
+            author: m.author.tag(),
+            at: m.timestamp,
+            content: m.content,
+            attachments: m.attachments.into_iter().map(|a| a.url).collect(),
+            embeds: m
+                .embeds
+                .into_iter()
+                .map(|e| {
+                    let mut lines: Vec<String> = e.title.into_iter().collect();
+                    lines.extend(e.description);
+                    lines.extend(
+                        e.fields
+                            .into_iter()
+                            .map(|f| format!("{}: {}", f.name, f.value)),
+                    );
+                    lines.extend(e.url);
+                    lines.join("\n")
+                })
+                .collect(),
+        })
+        .collect())
+}
 
 #[check]
-#[name = "InProtestChannel"]
-async fn protest_channel_check(
-    _ctx: &Context,
+#[name = "Steward"]
+async fn steward_check(
+    ctx: &Context,
     msg: &Message,
     _: &mut Args,
     _: &CommandOptions,
 ) -> Result<(), Reason> {
-    if config::CONFIG.protest_channels.contains(&msg.channel_id.0) {
+    let member = match msg.member(ctx).await {
+        Ok(member) => member,
+        Err(e) => return Err(Reason::Log(format!("Couldn't look up member: {:?}", e))),
+    };
+    // Without a steward role, stewarding is left to those who can manage channels
+    let steward = match settings::guild(msg.guild_id).protests.steward_role {
+        Some(role) => member.roles.contains(&RoleId(role)),
+        None => member
+            .permissions(ctx)
+            .await
+            .map(|permissions| permissions.manage_channels())
+            .unwrap_or(false),
+    };
+    // Stewards assigned to a protest may act on it without the steward role
+    let assigned = protest::get(msg.channel_id.0)
+        .is_some_and(|protest| protest.stewards.contains(&msg.author.id.0));
+    if steward || assigned {
         Ok(())
     } else {
-        warn!("Attempt to call !protest-channel in a non-whitelisted channel");
-        Err(Reason::Log(
-            "This command is only permitted in certain channels.".to_string(),
-        ))
+        Err(Reason::User("only stewards may do that.".to_string()))
     }
 }
 
 #[command]
-#[aliases("protest-channel")]
-#[checks(InProtestChannel)]
-async fn protest_channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let new_chan_name = args.single::<String>()?;
-    let source_channel = msg.channel_id.to_channel(ctx).await?.guild();
+#[aliases("reassign")]
+#[checks(Steward)]
+async fn protest_reassign(ctx: &Context, msg: &Message) -> CommandResult {
+    let protest = match protest_or_reply(ctx, msg).await? {
+        Some(protest) => protest,
+        None => return Ok(()),
+    };
+    let guild_settings = settings::guild(msg.guild_id);
+    let settings = guild_settings.protests;
+    let mut roster = guild_settings.stewards;
 
-    if let Some(source_channel) = source_channel {
-        let guild_name = source_channel
-            .guild_id
-            .name(ctx)
-            .await
-            .unwrap_or_else(|| "Unknown".to_string());
-        let target_category = source_channel.category_id;
-        info!(
-            "Creating channel \"{}\" for user {}",
-            new_chan_name, msg.author.name
-        );
-        let new_chan = source_channel
-            .guild_id
-            .create_channel(ctx, |c| {
-                let c = c.name(new_chan_name).kind(ChannelType::Text);
-                if let Some(category) = target_category {
-                    c.category(category)
-                } else {
-                    c
-                }
-            })
+    let chosen: Vec<u64> = msg.mentions.iter().map(|user| user.id.0).collect();
+    let stewards = if chosen.is_empty() {
+        // Hand the protest to someone new
+        roster
+            .stewards
+            .retain(|steward| !protest.stewards.contains(steward));
+        // The cursor is left alone, as it points into the whole roster
+        let (stewards, _) = protest::PROTESTS.read(|book| {
+            roster.assign(
+                settings
+                    .stewards_per_protest
+                    .unwrap_or(protest::DEFAULT_STEWARDS_PER_PROTEST),
+                settings.assignment,
+                protest.protester,
+                &protest.accused,
+                &book.steward_load(protest.guild),
+                book.steward_cursors
+                    .get(&protest.guild)
+                    .copied()
+                    .unwrap_or(0),
+            )
+        });
+        if stewards.is_empty() {
+            msg.channel_id
+                .say(
+                    ctx,
+                    format!(
+                        "{}, there are no other stewards on the roster who can hear this protest.",
+                        msg.author.mention()
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+        stewards
+    } else {
+        let conflicted: Vec<u64> = chosen
+            .iter()
+            .copied()
+            .filter(|&steward| roster.conflicted(steward, protest.protester, &protest.accused))
+            .collect();
+        if !conflicted.is_empty() {
+            msg.channel_id
+                .say(
+                    ctx,
+                    format!(
+                        "{}, {} can't hear this protest, as they're involved in it or on the same team as someone who is.",
+                        msg.author.mention(),
+                        mentions(&conflicted)
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+        chosen
+    };
+
+    // Let the new stewards in and those taken off it out, leaving anyone involved in the protest
+    let involved = |id: &u64| *id == protest.protester || protest.accused.contains(id);
+    for &steward in stewards.iter().filter(|id| !protest.stewards.contains(id)) {
+        msg.channel_id
+            .create_permission(ctx, &protest::steward_overwrite(steward))
+            .await?;
+    }
+    for &steward in protest
+        .stewards
+        .iter()
+        .filter(|id| !stewards.contains(id) && !involved(id))
+    {
+        msg.channel_id
+            .delete_permission(ctx, PermissionOverwriteType::Member(UserId(steward)))
             .await?;
+    }
 
-        let remaining_text = args.remains();
-
-        if remaining_text.is_some() || !msg.embeds.is_empty() {
-            info!("Sending intro message to channel");
-            // Serenity only support setting one embed on an outbound message so we just take the
-            // first from that which triggered us
-            let embed: Option<CreateEmbed> = msg.embeds.first().cloned().map(Into::into);
-
-            // It's possible that the new channel isn't available yet so we may need to wait for it
-            // to appear in the cache.
-            let concrete = wait_for_channel(ctx, &new_chan).await?;
-            let intro_message = concrete
-                .send_message(ctx, |mut m| {
-                    if let Some(text) = remaining_text {
-                        m = m.content(text);
-                        info!("Intro text: {}", text);
-                    }
-                    if let Some(embed) = embed {
-                        m = m.set_embed(embed);
-                    }
-                    m
-                })
-                .await?;
+    protest::update(protest.channel, |protest| {
+        protest.stewards = stewards.clone()
+    })?;
+    info!(
+        "Protest in channel {} reassigned by user {}",
+        msg.channel_id, msg.author.name
+    );
+    msg.channel_id
+        .say(
+            ctx,
+            format!("Stewards for this protest: {}", mentions(&stewards)),
+        )
+        .await?;
+    Ok(())
+}
 
-            info!("Created intro message: {:?}", intro_message);
-            info!("Attempting to pin intro message.");
-            intro_message.pin(ctx).await?;
-        }
+#[command]
+#[aliases("config")]
+#[required_permissions("MANAGE_GUILD")]
+async fn protest_config(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
 
-        metrics::PROTEST_CHANNELS_CREATED
-            .with_label_values(&[&msg.author.name, &guild_name])
-            .inc();
+    let mut protests = settings::guild(Some(guild_id)).protests;
+    let reply = if args.is_empty() {
+        protests.describe()
     } else {
-        error!("Received a create-channel message from a non-guild context");
+        match protests.apply(args.rest()) {
+            Ok(()) => {
+                settings::GUILDS.update(|guilds| {
+                    guilds.entry(guild_id.0).or_default().protests = protests.clone()
+                })?;
+                info!("Updated protest settings for guild {}", guild_id);
+                protests.describe()
+            }
+            Err(e) => format!(
+                "{}. A server can set `{}`, e.g. `!protest config steward_role=@Stewards accused=read`.",
+                e,
+                protest::SETTING_KEYS.join("`, `")
+            ),
+        }
+    };
+
+    msg.channel_id
+        .say(ctx, format!("{}, {}", msg.author.mention(), reply))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("status")]
+#[checks(Steward)]
+async fn protest_status(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let protest = match protest::get(msg.channel_id.0) {
+        Some(protest) => protest,
+        None => {
+            msg.channel_id
+                .say(
+                    ctx,
+                    format!(
+                        "{}, this channel isn't for a protest we know about.",
+                        msg.author.mention()
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    if args.is_empty() {
+        let mut history = format!(
+            "{}, this protest is **{}**.",
+            msg.author.mention(),
+            protest.status.name()
+        );
+        for change in &protest.changes {
+            history.push_str(&format!(
+                "\n{}: {} by {}",
+                change.at.format("%Y-%m-%d %H:%M UTC"),
+                change.status.name(),
+                UserId(change.by).mention()
+            ));
+        }
+        msg.channel_id.say(ctx, history).await?;
+        return Ok(());
     }
 
+    let now = Utc::now();
+    let updated = match ProtestStatus::parse(args.rest()) {
+        Ok(status) => protest::update(protest.channel, |protest| {
+            protest.set_status(status, msg.author.id.0, now)?;
+            Ok(protest.clone())
+        })?,
+        Err(e) => Some(Err(e)),
+    };
+    let protest = match updated {
+        Some(Ok(protest)) => protest,
+        None => return Ok(()),
+        Some(Err(e)) => {
+            msg.channel_id
+                .say(ctx, format!("{}, {}.", msg.author.mention(), e))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    info!(
+        "Protest in channel {} moved to {} by user {}",
+        msg.channel_id,
+        protest.status.name(),
+        msg.author.name
+    );
+
+    rename_protest_channel(ctx, &protest).await;
+    msg.channel_id
+        .say(
+            ctx,
+            format!(
+                "{}, this protest is now **{}**.",
+                msg.author.mention(),
+                protest.status.name()
+            ),
+        )
+        .await?;
     Ok(())
 }
 
@@ -190,6 +2352,23 @@ async fn wait_for_channel<C: Into<ChannelId>>(
     Err(CommandErr::ChannelCreationLost)
 }
 
+/// Tell the user why a check stopped their command, where the check has said
+#[hook]
+async fn dispatch_error(ctx: &Context, msg: &Message, error: DispatchError) {
+    if let DispatchError::CheckFailed(_, Reason::User(reason)) = error {
+        if let Err(e) = msg
+            .channel_id
+            .say(ctx, format!("{}, {}", msg.author.mention(), reason))
+            .await
+        {
+            warn!(
+                "Failed to explain failed check to {}: {:?}",
+                msg.author.name, e
+            );
+        }
+    }
+}
+
 #[hook]
 async fn after(ctx: &Context, msg: &Message, command_name: &str, command_result: CommandResult) {
     if let Err(ref e) = command_result {
@@ -238,13 +2417,27 @@ async fn my_help(
     Ok(())
 }
 
+/// Every group of commands the bot answers to
+static GROUPS: &[&CommandGroup] = &[
+    &PROTEST_GROUP,
+    &PROTESTS_GROUP,
+    &VOTE_GROUP,
+    &DECISIONS_GROUP,
+    &POINTS_GROUP,
+    &SESSIONS_GROUP,
+    &STEWARDS_GROUP,
+    &STRAT_GROUP,
+    &RACE_GROUP,
+];
+
 pub async fn create_client(token: &str) -> Client {
-    let framework = StandardFramework::new()
+    let mut framework = StandardFramework::new()
         .configure(|c| c.prefix("!").ignore_webhooks(false).ignore_bots(false))
-        .help(&MY_HELP)
-        .group(&PROTEST_GROUP)
-        .group(&STRAT_GROUP)
-        .after(after);
+        .help(&MY_HELP);
+    for group in GROUPS {
+        framework = framework.group(group);
+    }
+    let framework = framework.on_dispatch_error(dispatch_error).after(after);
 
     // Login with a bot token from the environment
     let client = Client::builder(token)
@@ -254,3 +2447,57 @@ pub async fn create_client(token: &str) -> Client {
 
     client
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The names each command in a group answers to
+    fn names(group: &CommandGroup) -> Vec<&'static str> {
+        group
+            .options
+            .commands
+            .iter()
+            .chain(&group.options.default_command)
+            .flat_map(|command| command.options.names.iter().copied())
+            .collect()
+    }
+
+    #[test]
+    fn race_commands_are_registered() {
+        assert!(GROUPS.contains(&&RACE_GROUP));
+        let names = names(&RACE_GROUP);
+        for name in &[
+            "start",
+            "status",
+            "pause",
+            "resume",
+            "adjust",
+            "stop",
+            "telemetry",
+        ] {
+            assert!(names.contains(name), "!race {} isn't registered", name);
+        }
+    }
+
+    #[test]
+    fn command_names_are_unambiguous() {
+        for group in GROUPS {
+            let mut names: Vec<&str> = group
+                .options
+                .commands
+                .iter()
+                .flat_map(|command| command.options.names.iter().copied())
+                .collect();
+            let count = names.len();
+            names.sort_unstable();
+            names.dedup();
+            assert_eq!(
+                count,
+                names.len(),
+                "two commands in {} share a name",
+                group.name
+            );
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+use stewart_strat::{format_hms, Strategy, StrategyInput};
+use thiserror::Error;
+
+use crate::store::{Store, StoreError};
+
+/// How many calculations we remember for each user, dropping the oldest first
+const MAX_PER_USER: usize = 25;
+
+/// Start IDs at four characters so they all look alike for a good while
+const FIRST_ID: u64 = 36 * 36 * 36;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PlanError {
+    #[error("`{0}` has more than one plan, give its number after the ID: {1}")]
+    Ambiguous(String, String),
+    #[error("`{0}` has no plan `{1}`, give one of {2}")]
+    Unknown(String, String, String),
+}
+
+/// A strategy calculation, kept so it can be shown again or re-run with changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedStrategy {
+    pub id: String,
+    pub user: u64,
+    pub guild: Option<u64>,
+    pub created: DateTime<Utc>,
+    pub input: StrategyInput,
+    pub strategies: Vec<Strategy>,
+}
+
+impl SavedStrategy {
+    /// A one line reminder of what was calculated
+    pub fn summary(&self) -> String {
+        format!(
+            "`{}` {} race, {}:{:02} laps, {} per lap, {} {} tank ({})",
+            self.id,
+            format_hms(self.input.race_duration),
+            self.input.avg_laptime.as_secs() / 60,
+            self.input.avg_laptime.as_secs() % 60,
+            self.input.fuel_per_lap,
+            self.input.fuel_capacity,
+            self.input.fuel_units.unit.symbol(),
+            self.created.format("%Y-%m-%d %H:%M UTC")
+        )
+    }
+
+    /// The strategy numbered `choice`, counting from 1, which may be left out if there's only one
+    pub fn plan(&self, choice: Option<&str>) -> Result<&Strategy, PlanError> {
+        let plans = || {
+            self.strategies
+                .iter()
+                .enumerate()
+                .map(|(i, strategy)| format!("{}. {}", i + 1, strategy.title()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match choice {
+            None if self.strategies.len() > 1 => {
+                Err(PlanError::Ambiguous(self.id.clone(), plans()))
+            }
+            None => self
+                .strategies
+                .first()
+                .ok_or_else(|| PlanError::Unknown(self.id.clone(), "1".to_string(), plans())),
+            Some(choice) => choice
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| self.strategies.get(n.checked_sub(1)?))
+                .ok_or_else(|| PlanError::Unknown(self.id.clone(), choice.to_string(), plans())),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    next_id: u64,
+    entries: Vec<SavedStrategy>,
+}
+
+impl History {
+    /// Find a calculation by its ID among those `user` may see in `guild`: their own, and those
+    /// made by others in the same server
+    pub fn find(&self, id: &str, user: u64, guild: Option<u64>) -> Option<&SavedStrategy> {
+        let id = id.to_lowercase();
+        self.entries.iter().find(|entry| {
+            entry.id == id && (entry.user == user || (guild.is_some() && entry.guild == guild))
+        })
+    }
+}
+
+lazy_static! {
+    pub static ref HISTORY: Store<History> = Store::open("history.json");
+}
+
+/// Remember a calculation, returning the ID it can be recalled with
+pub fn save(
+    user: UserId,
+    guild: Option<GuildId>,
+    input: StrategyInput,
+    strategies: Vec<Strategy>,
+) -> Result<String, StoreError> {
+    HISTORY.update(|history| {
+        let id = to_base36(history.next_id.max(FIRST_ID));
+        history.next_id = history.next_id.max(FIRST_ID) + 1;
+
+        history.entries.push(SavedStrategy {
+            id: id.clone(),
+            user: user.0,
+            guild: guild.map(|guild| guild.0),
+            created: Utc::now(),
+            input,
+            strategies,
+        });
+
+        let saved = history
+            .entries
+            .iter()
+            .filter(|entry| entry.user == user.0)
+            .count();
+        if saved > MAX_PER_USER {
+            if let Some(oldest) = history
+                .entries
+                .iter()
+                .position(|entry| entry.user == user.0)
+            {
+                history.entries.remove(oldest);
+            }
+        }
+
+        id
+    })
+}
+
+/// Find a calculation `user` may see by its ID, ignoring case as people will type it however
+/// they like
+pub fn get(id: &str, user: UserId, guild: Option<GuildId>) -> Option<SavedStrategy> {
+    HISTORY.read(|history| {
+        history
+            .find(id, user.0, guild.map(|guild| guild.0))
+            .cloned()
+    })
+}
+
+/// A user's most recent calculations, newest first
+pub fn recent(user: UserId, count: usize) -> Vec<SavedStrategy> {
+    HISTORY.read(|history| {
+        history
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.user == user.0)
+            .take(count)
+            .cloned()
+            .collect()
+    })
+}
+
+fn to_base36(mut n: u64) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut digits = vec![];
+    loop {
+        digits.push(DIGITS[(n % 36) as usize]);
+        n /= 36;
+        if n == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    String::from_utf8(digits).expect("base 36 digits are ASCII")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ids_are_short_base36() {
+        assert_eq!("0", to_base36(0));
+        assert_eq!("z", to_base36(35));
+        assert_eq!("1000", to_base36(FIRST_ID));
+        assert_eq!("zzzz", to_base36(36 * 36 * 36 * 36 - 1));
+    }
+
+    #[test]
+    fn finds_only_what_the_user_may_see() {
+        let saved = |id: &str, user, guild| SavedStrategy {
+            id: id.to_string(),
+            user,
+            guild,
+            created: Utc::now(),
+            input: StrategyInput::builder(
+                std::time::Duration::new(3600, 0),
+                std::time::Duration::new(120, 0),
+                3.0,
+                100.0,
+            )
+            .build()
+            .unwrap(),
+            strategies: vec![],
+        };
+        let history = History {
+            next_id: FIRST_ID + 2,
+            entries: vec![saved("1000", 1, Some(10)), saved("1001", 2, None)],
+        };
+
+        assert!(history.find("1000", 1, None).is_some());
+        assert!(history.find("1000", 3, Some(10)).is_some());
+        assert!(history.find("1000", 3, Some(20)).is_none());
+        assert!(history.find("1000", 3, None).is_none());
+        assert!(history.find("1001", 2, Some(10)).is_some());
+        assert!(history.find("1001", 3, None).is_none());
+    }
+
+    #[test]
+    fn picks_a_plan_by_number() {
+        let input = StrategyInput::builder(
+            std::time::Duration::new(14400, 0),
+            std::time::Duration::new(138, 0),
+            3.9,
+            110.0,
+        )
+        .build()
+        .unwrap();
+        let mut saved = SavedStrategy {
+            id: "1000".to_string(),
+            user: 1,
+            guild: None,
+            created: Utc::now(),
+            strategies: input.calculate(),
+            input,
+        };
+        assert_eq!(2, saved.strategies.len());
+
+        assert!(matches!(saved.plan(None), Err(PlanError::Ambiguous(..))));
+        assert_eq!("Equal Stints", saved.plan(Some("2")).unwrap().title());
+        for choice in &["0", "3", "two"] {
+            assert!(matches!(
+                saved.plan(Some(choice)),
+                Err(PlanError::Unknown(..))
+            ));
+        }
+
+        saved.strategies.truncate(1);
+        assert_eq!(
+            saved.strategies[0].title(),
+            saved.plan(None).unwrap().title()
+        );
+    }
+}
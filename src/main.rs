@@ -5,22 +5,57 @@ use warp::Filter;
 
 mod config;
 mod discord;
+mod history;
 mod metrics;
+mod pages;
+mod penalty;
+mod points;
+mod protest;
+mod race;
+mod session;
+mod settings;
+mod stewards;
+mod store;
 mod strategy;
+mod telemetry;
+mod transcript;
+mod vote;
 
 #[tokio::main]
 async fn main() {
     pretty_env_logger::init();
 
+    // Load saved data up front, so a bad file stops us here rather than in the middle of a command
+    lazy_static::initialize(&settings::GUILDS);
+    lazy_static::initialize(&history::HISTORY);
+    lazy_static::initialize(&settings::SERIES);
+    lazy_static::initialize(&penalty::PENALTIES);
+    lazy_static::initialize(&race::RACES);
+    lazy_static::initialize(&protest::PROTESTS);
+    lazy_static::initialize(&points::POINTS);
+    lazy_static::initialize(&session::SESSIONS);
+
     let mut discord_client = discord::create_client(&config::CONFIG.discord_token).await;
     info!("Created Discord client successfully");
 
+    // Call out stops for any races still running from before a restart, as well as new ones
+    let http = discord_client.cache_and_http.http.clone();
+    tokio::spawn(discord::race_reminders(http.clone()));
+    tokio::spawn(discord::vote_deadlines(http.clone()));
+
     // start listening for events by starting a single shard
     let discord_shard = discord_client.start();
 
     // Set up the HTTP handlers
     let metrics_route = warp::path!("metrics").and_then(metrics::metrics_handler);
-    let warp_future = warp::serve(metrics_route)
+    let telemetry_route = warp::path!("telemetry" / u64)
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(4096))
+        .and(warp::body::json())
+        .and(warp::any().map(move || http.clone()))
+        .and_then(telemetry::telemetry_handler);
+    let warp_future = warp::serve(metrics_route.or(telemetry_route))
         .bind(config::CONFIG.socket_addr)
         .map(Ok);
 
@@ -0,0 +1,285 @@
+use futures::StreamExt;
+use serenity::builder::CreateEmbed;
+use serenity::client::Context;
+use serenity::framework::standard::CommandResult;
+use serenity::model::channel::{Message, ReactionType};
+use serenity::prelude::*;
+use std::time::Duration;
+
+use log::{info, warn};
+
+// Discord rejects embeds which break any of these limits
+const FIELD_VALUE_LIMIT: usize = 1024;
+const FIELD_LIMIT: usize = 25;
+const EMBED_LIMIT: usize = 6000;
+// Leave room for the page number in the footer
+const FOOTER_ALLOWANCE: usize = 32;
+
+const PREVIOUS_PAGE: &str = "◀️";
+const NEXT_PAGE: &str = "▶️";
+const NAVIGATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone)]
+struct Field {
+    name: String,
+    value: String,
+    inline: bool,
+}
+
+impl Field {
+    fn len(&self) -> usize {
+        self.name.chars().count() + self.value.chars().count()
+    }
+}
+
+/// An embed which spreads its fields over as many pages as it needs to stay within Discord's
+/// limits, with reactions to move between them.
+#[derive(Debug, Clone)]
+pub struct PagedEmbed {
+    title: String,
+    description: String,
+    pages: Vec<Vec<Field>>,
+}
+
+impl PagedEmbed {
+    pub fn new<T: ToString, D: ToString>(title: T, description: D) -> Self {
+        PagedEmbed {
+            title: title.to_string(),
+            description: description.to_string(),
+            pages: vec![vec![]],
+        }
+    }
+
+    /// Add a field, breaking it up between paragraphs if it's too long for one
+    pub fn field<N: ToString>(&mut self, name: N, value: &str, inline: bool) {
+        let name = name.to_string();
+        for (i, chunk) in split_text(value, FIELD_VALUE_LIMIT).into_iter().enumerate() {
+            self.push(Field {
+                name: continued(&name, i),
+                value: chunk,
+                inline,
+            });
+        }
+    }
+
+    /// Add a field laid out as a code block, repeating the block on each part if it's too long
+    pub fn code_field<N: ToString>(&mut self, name: N, code: &str) {
+        let name = name.to_string();
+        // Each part needs room for its own fences
+        let limit = FIELD_VALUE_LIMIT - "```\n\n```".len();
+        for (i, chunk) in split_lines(code, limit).into_iter().enumerate() {
+            self.push(Field {
+                name: continued(&name, i),
+                value: format!("```\n{}\n```", chunk),
+                inline: false,
+            });
+        }
+    }
+
+    fn push(&mut self, field: Field) {
+        let budget = EMBED_LIMIT
+            - FOOTER_ALLOWANCE
+            - self.title.chars().count()
+            - self.description.chars().count();
+
+        let page = self.pages.last_mut().expect("There's always one page");
+        let used: usize = page.iter().map(Field::len).sum();
+        if !page.is_empty() && (page.len() == FIELD_LIMIT || used + field.len() > budget) {
+            self.pages.push(vec![field]);
+        } else {
+            page.push(field);
+        }
+    }
+
+    fn render(&self, e: &mut CreateEmbed, page: usize) {
+        e.title(&self.title);
+        e.description(&self.description);
+        for field in &self.pages[page] {
+            e.field(&field.name, &field.value, field.inline);
+        }
+        if self.pages.len() > 1 {
+            e.footer(|f| f.text(format!("Page {} of {}", page + 1, self.pages.len())));
+        }
+    }
+
+    /// Reply to `msg` with the first page, then let its author flip through the rest
+    pub async fn send(self, ctx: &Context, msg: &Message) -> CommandResult {
+        let mut message = msg
+            .channel_id
+            .send_message(ctx, |m| {
+                m.content(msg.author.mention());
+                m.embed(|e| {
+                    self.render(e, 0);
+                    e
+                });
+                m
+            })
+            .await?;
+
+        if self.pages.len() == 1 {
+            return Ok(());
+        }
+
+        for emoji in &[PREVIOUS_PAGE, NEXT_PAGE] {
+            message
+                .react(ctx, ReactionType::Unicode(emoji.to_string()))
+                .await?;
+        }
+
+        // Wait for page turns in the background so the command can finish
+        let ctx = ctx.clone();
+        let author = msg.author.id;
+        tokio::spawn(async move {
+            let mut reactions = message
+                .await_reactions(&ctx)
+                .author_id(author)
+                .removed(true)
+                .timeout(NAVIGATION_TIMEOUT)
+                .await;
+
+            let mut page = 0;
+            while let Some(action) = reactions.next().await {
+                let emoji = &action.as_inner_ref().emoji;
+                let next = if emoji.unicode_eq(NEXT_PAGE) {
+                    (page + 1) % self.pages.len()
+                } else if emoji.unicode_eq(PREVIOUS_PAGE) {
+                    (page + self.pages.len() - 1) % self.pages.len()
+                } else {
+                    continue;
+                };
+
+                page = next;
+                info!("Showing page {} of strategy to {}", page + 1, author);
+                let edited = message
+                    .edit(&ctx, |m| {
+                        m.embed(|e| {
+                            self.render(e, page);
+                            e
+                        })
+                    })
+                    .await;
+                if let Err(e) = edited {
+                    warn!("Failed to turn page of strategy output: {:?}", e);
+                    break;
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+fn continued(name: &str, part: usize) -> String {
+    if part == 0 {
+        name.to_string()
+    } else {
+        format!("{} (cont.)", name)
+    }
+}
+
+/// Break text into chunks no longer than `limit`, preferring to split between paragraphs
+fn split_text(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+
+    for paragraph in text.split("\n\n") {
+        let needed = if current.is_empty() {
+            paragraph.chars().count()
+        } else {
+            current.chars().count() + 2 + paragraph.chars().count()
+        };
+
+        if needed <= limit {
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            current.push_str(paragraph);
+            continue;
+        }
+
+        if !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if paragraph.chars().count() <= limit {
+            current.push_str(paragraph);
+        } else {
+            chunks.extend(split_lines(paragraph, limit));
+        }
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Break text into chunks no longer than `limit`, splitting between lines where possible
+fn split_lines(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+
+    for line in text.lines() {
+        let mut line = line.to_string();
+        // A single line too long for a chunk has to be cut wherever it reaches the limit
+        while line.chars().count() > limit {
+            let rest = line.split_off(line.char_indices().nth(limit).unwrap().0);
+            if !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+            }
+            chunks.push(line);
+            line = rest;
+        }
+
+        if !current.is_empty() && current.chars().count() + 1 + line.chars().count() > limit {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(&line);
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_between_paragraphs() {
+        let text = "aaaa\n\nbbbb\n\ncccc";
+        assert_eq!(vec!["aaaa\n\nbbbb", "cccc"], split_text(text, 10));
+        assert_eq!(vec![text.to_string()], split_text(text, 100));
+    }
+
+    #[test]
+    fn splits_long_lines() {
+        assert_eq!(vec!["abc", "def", "gh"], split_lines("abcdefgh", 3));
+        assert_eq!(vec!["ab\ncd", "ef"], split_lines("ab\ncd\nef", 5));
+    }
+
+    #[test]
+    fn long_races_span_several_pages() {
+        let stint =
+            "**Stint 1**\n1h 2m 3s\nStarts <t:1619888400:t>\n\n**Stop 1**\nLap 27\nAdd fuel: 106 L";
+        let text = vec![stint; 60].join("\n\n");
+
+        let mut embed = PagedEmbed::new("Strategy Calculator", "We calculated 2 strategies");
+        embed.field("Longer Stints", &text, true);
+        embed.field("Equal Stints", &text, true);
+
+        assert!(embed.pages.len() > 1);
+        for page in &embed.pages {
+            assert!(page.len() <= FIELD_LIMIT);
+            assert!(page.iter().map(Field::len).sum::<usize>() <= EMBED_LIMIT);
+            assert!(page
+                .iter()
+                .all(|f| f.value.chars().count() <= FIELD_VALUE_LIMIT));
+        }
+        assert_eq!("Longer Stints (cont.)", embed.pages[0][1].name);
+    }
+}
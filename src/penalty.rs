@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+lazy_static! {
+    pub static ref PENALTIES: PenaltyCatalogue = load_penalties();
+}
+
+/// What a penalty does to the driver who gets it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PenaltyKind {
+    Warning,
+    /// Seconds added to the driver's race time
+    Time(u32),
+    DriveThrough,
+    /// Places dropped on the grid for the next race
+    GridDrop(u32),
+    Disqualification,
+}
+
+impl PenaltyKind {
+    pub fn describe(&self) -> String {
+        match self {
+            PenaltyKind::Warning => "Warning".to_string(),
+            PenaltyKind::Time(secs) => format!("{} second time penalty", secs),
+            PenaltyKind::DriveThrough => "Drive-through penalty".to_string(),
+            PenaltyKind::GridDrop(places) => format!("{} place grid drop", places),
+            PenaltyKind::Disqualification => "Disqualification".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Penalty {
+    pub name: String,
+    pub kind: PenaltyKind,
+    /// Penalty points which come with the penalty
+    #[serde(default)]
+    pub points: u32,
+}
+
+/// The penalties stewards may hand out, by ID
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PenaltyCatalogue(BTreeMap<String, Penalty>);
+
+impl PenaltyCatalogue {
+    /// Read penalties from JSON, keyed by ID, e.g.
+    /// `{"5s": {"name": "5 second penalty", "kind": {"time": 5}}, "dsq": {"name": "Disqualified", "kind": "disqualification"}}`
+    pub fn from_json(json: &str) -> serde_json::Result<PenaltyCatalogue> {
+        serde_json::from_str(json)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Penalty> {
+        self.0.get(&id.to_lowercase())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Penalty)> {
+        self.0.iter()
+    }
+}
+
+impl Default for PenaltyCatalogue {
+    fn default() -> PenaltyCatalogue {
+        let penalties = vec![
+            ("warning", PenaltyKind::Warning, 0),
+            ("5s", PenaltyKind::Time(5), 1),
+            ("10s", PenaltyKind::Time(10), 2),
+            ("30s", PenaltyKind::Time(30), 3),
+            ("drive-through", PenaltyKind::DriveThrough, 3),
+            ("grid-3", PenaltyKind::GridDrop(3), 2),
+            ("grid-5", PenaltyKind::GridDrop(5), 3),
+            ("dsq", PenaltyKind::Disqualification, 5),
+        ];
+        PenaltyCatalogue(
+            penalties
+                .into_iter()
+                .map(|(id, kind, points)| {
+                    let name = kind.describe();
+                    (id.to_string(), Penalty { name, kind, points })
+                })
+                .collect(),
+        )
+    }
+}
+
+fn load_penalties() -> PenaltyCatalogue {
+    let path = &config::CONFIG.penalties_file;
+    match fs::read_to_string(path) {
+        Ok(json) => match PenaltyCatalogue::from_json(&json) {
+            Ok(penalties) => penalties,
+            Err(e) => panic!("Failed to load {}: {:#?}", path.display(), e),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            info!(
+                "No penalty catalogue at {}, using the defaults",
+                path.display()
+            );
+            PenaltyCatalogue::default()
+        }
+        Err(e) => panic!("Failed to load {}: {:#?}", path.display(), e),
+    }
+}
+
+/// A penalty the stewards have given a driver
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Decision {
+    /// ID of the penalty in the catalogue
+    pub penalty: String,
+    /// The penalty as it was when given, in case the catalogue changes later
+    pub name: String,
+    pub kind: PenaltyKind,
+    #[serde(default)]
+    pub points: u32,
+    pub driver: u64,
+    pub reason: String,
+    pub by: u64,
+    pub at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_the_catalogue() {
+        let penalties = PenaltyCatalogue::from_json(
+            r#"{"5s": {"name": "Five seconds", "kind": {"time": 5}}, "dt": {"name": "Drive through", "kind": "drive_through"}, "grid": {"name": "Grid penalty", "kind": {"grid_drop": 3}}}"#,
+        )
+        .unwrap();
+        assert_eq!(PenaltyKind::Time(5), penalties.get("5S").unwrap().kind);
+        assert_eq!(0, penalties.get("5s").unwrap().points);
+        assert_eq!(PenaltyKind::DriveThrough, penalties.get("dt").unwrap().kind);
+        assert_eq!(
+            PenaltyKind::GridDrop(3),
+            penalties.get("grid").unwrap().kind
+        );
+        assert!(penalties.get("dsq").is_none());
+
+        let defaults = PenaltyCatalogue::default();
+        assert_eq!("10 second time penalty", defaults.get("10s").unwrap().name);
+        assert_eq!(
+            PenaltyKind::Disqualification,
+            defaults.get("dsq").unwrap().kind
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::store::Store;
+
+lazy_static! {
+    /// Each guild's penalty points, by guild
+    pub static ref POINTS: Store<HashMap<u64, PointsLedger>> = Store::open("points.json");
+}
+
+/// Most days points may count for, about ten years
+pub const MAX_EXPIRY_DAYS: u32 = 3650;
+
+/// How long penalty points count against a driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PointsExpiry {
+    Days(u32),
+    /// Events held after the points were given
+    Events(u32),
+}
+
+impl PointsExpiry {
+    /// Read an expiry such as `365d`, `365days`, `4e` or `4events`, of up to `MAX_EXPIRY_DAYS`
+    pub fn parse(s: &str) -> Option<PointsExpiry> {
+        let (amount, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit())?);
+        let amount: u32 = amount.parse().ok().filter(|&amount| amount > 0)?;
+        match unit.to_lowercase().as_str() {
+            "d" | "day" | "days" if amount <= MAX_EXPIRY_DAYS => Some(PointsExpiry::Days(amount)),
+            "e" | "event" | "events" => Some(PointsExpiry::Events(amount)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PointsExpiry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PointsExpiry::Days(days) => write!(f, "{} days", days),
+            PointsExpiry::Events(events) => write!(f, "{} events", events),
+        }
+    }
+}
+
+/// Points given to a driver with a penalty
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PointsEntry {
+    pub driver: u64,
+    pub points: u32,
+    /// Name of the penalty the points came with
+    pub penalty: String,
+    pub reason: String,
+    /// Channel of the protest the points were given in
+    pub protest: u64,
+    pub at: DateTime<Utc>,
+    /// Whether the points have been wiped by a race ban
+    #[serde(default)]
+    pub cleared: bool,
+}
+
+/// A race ban for reaching the points threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ban {
+    pub driver: u64,
+    pub points: u32,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PointsLedger {
+    #[serde(default)]
+    pub entries: Vec<PointsEntry>,
+    /// When each event was held, which points expiring after a number of events count
+    #[serde(default)]
+    pub events: Vec<DateTime<Utc>>,
+    #[serde(default)]
+    pub bans: Vec<Ban>,
+}
+
+impl PointsLedger {
+    /// Whether an entry still counts against its driver
+    pub fn is_active(
+        &self,
+        entry: &PointsEntry,
+        expiry: Option<PointsExpiry>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        !entry.cleared
+            && match expiry {
+                None => true,
+                // Points which would expire beyond the end of time never do
+                Some(PointsExpiry::Days(days)) => entry
+                    .at
+                    .checked_add_signed(Duration::days(days.min(MAX_EXPIRY_DAYS).into()))
+                    .is_none_or(|expires| now < expires),
+                Some(PointsExpiry::Events(events)) => {
+                    self.events.iter().filter(|&&held| held > entry.at).count() < events as usize
+                }
+            }
+    }
+
+    /// Points counting against `driver`
+    pub fn active_points(
+        &self,
+        driver: u64,
+        expiry: Option<PointsExpiry>,
+        now: DateTime<Utc>,
+    ) -> u32 {
+        self.history(driver)
+            .filter(|entry| self.is_active(entry, expiry, now))
+            .map(|entry| entry.points)
+            .sum()
+    }
+
+    /// Every entry for `driver`, oldest first
+    pub fn history(&self, driver: u64) -> impl Iterator<Item = &PointsEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.driver == driver)
+    }
+
+    /// Give a driver points, banning them if that takes them to `threshold`. A ban wipes the
+    /// points which led to it.
+    pub fn add(
+        &mut self,
+        entry: PointsEntry,
+        expiry: Option<PointsExpiry>,
+        threshold: Option<u32>,
+        now: DateTime<Utc>,
+    ) -> Option<Ban> {
+        let driver = entry.driver;
+        self.entries.push(entry);
+        let points = self.active_points(driver, expiry, now);
+        if points < threshold? {
+            return None;
+        }
+
+        let active: Vec<bool> = self
+            .entries
+            .iter()
+            .map(|entry| entry.driver == driver && self.is_active(entry, expiry, now))
+            .collect();
+        for (entry, active) in self.entries.iter_mut().zip(active) {
+            entry.cleared |= active;
+        }
+        let ban = Ban {
+            driver,
+            points,
+            at: now,
+        };
+        self.bans.push(ban.clone());
+        Some(ban)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(points: u32, at: DateTime<Utc>) -> PointsEntry {
+        PointsEntry {
+            driver: 100,
+            points,
+            penalty: "5 second time penalty".to_string(),
+            reason: "Collision".to_string(),
+            protest: 1,
+            at,
+            cleared: false,
+        }
+    }
+
+    #[test]
+    fn points_expire() {
+        let now = Utc::now();
+        let mut ledger = PointsLedger::default();
+        ledger.entries.push(entry(2, now - Duration::days(40)));
+        ledger.entries.push(entry(3, now - Duration::days(10)));
+
+        assert_eq!(5, ledger.active_points(100, None, now));
+        assert_eq!(
+            3,
+            ledger.active_points(100, Some(PointsExpiry::Days(30)), now)
+        );
+        assert_eq!(0, ledger.active_points(200, None, now));
+
+        ledger.events.push(now - Duration::days(20));
+        let expiry = Some(PointsExpiry::Events(1));
+        assert_eq!(3, ledger.active_points(100, expiry, now));
+
+        assert_eq!(
+            Some(PointsExpiry::Days(365)),
+            PointsExpiry::parse("365days")
+        );
+        assert_eq!(Some(PointsExpiry::Events(4)), PointsExpiry::parse("4e"));
+        assert_eq!(None, PointsExpiry::parse("4weeks"));
+        assert_eq!(None, PointsExpiry::parse("3651d"));
+        assert_eq!(None, PointsExpiry::parse("99999999999d"));
+
+        let mut old = entry(1, chrono::MAX_DATETIME);
+        old.driver = 300;
+        ledger.entries.push(old);
+        assert_eq!(
+            1,
+            ledger.active_points(300, Some(PointsExpiry::Days(MAX_EXPIRY_DAYS)), now)
+        );
+    }
+
+    #[test]
+    fn bans_at_the_threshold() {
+        let now = Utc::now();
+        let mut ledger = PointsLedger::default();
+        assert!(ledger.add(entry(3, now), None, Some(5), now).is_none());
+        assert!(ledger.add(entry(1, now), None, None, now).is_none());
+
+        let ban = ledger.add(entry(1, now), None, Some(5), now).unwrap();
+        assert_eq!(5, ban.points);
+        assert_eq!(0, ledger.active_points(100, None, now));
+        assert_eq!(3, ledger.history(100).count());
+
+        // The slate is clean after a ban
+        assert!(ledger.add(entry(3, now), None, Some(5), now).is_none());
+        assert_eq!(1, ledger.bans.len());
+    }
+}
@@ -0,0 +1,958 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serenity::model::channel::{PermissionOverwrite, PermissionOverwriteType};
+use serenity::model::id::{RoleId, UserId};
+use serenity::model::Permissions;
+use serenity::utils::{parse_channel, parse_role, parse_username};
+use thiserror::Error;
+use url::Url;
+
+use crate::penalty::Decision;
+use crate::points::PointsExpiry;
+use crate::stewards::Assignment;
+use crate::store::{Store, StoreError};
+use crate::vote::Vote;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ProtestError {
+    #[error("a protest can't go from {} to {}", .0.name(), .1.name())]
+    Transition(ProtestStatus, ProtestStatus),
+    #[error("unknown status {0}, try one of {}", ProtestStatus::ALL.iter().map(|s| s.id()).collect::<Vec<_>>().join(", "))]
+    UnknownStatus(String),
+    #[error("the protest needs `{0}=`")]
+    MissingField(&'static str),
+    #[error("`{0}={1}` isn't valid, {2}")]
+    InvalidField(String, String, &'static str),
+    #[error("`{0}` isn't a detail of a protest, try {}", FORM_FIELDS.join(", "))]
+    UnknownField(String),
+    #[error("a quote in the protest is never closed")]
+    UnclosedQuote,
+    #[error("`{0}` isn't a protest setting, try {}", SETTING_KEYS.join(", "))]
+    UnknownSetting(String),
+    #[error(
+        "`{{{0}}}` can't go in a channel name, try {{seq}}, {{seq:04}}, {{round}} or {{name}}"
+    )]
+    UnknownPlaceholder(String),
+    #[error("there's already a protest called {0}")]
+    DuplicateName(String),
+}
+
+/// Name given to protests when the guild hasn't set a template
+pub const DEFAULT_NAME_TEMPLATE: &str = "{name}";
+
+/// Longest protest name, leaving room for the status prefix in Discord's 100 character limit
+const MAX_NAME: usize = 90;
+
+/// Make a protest name from a template, filling in `{seq}`, which may be zero padded as in
+/// `{seq:04}`, `{round}` and `{name}`
+pub fn render_name(
+    template: &str,
+    seq: u32,
+    round: &str,
+    name: &str,
+) -> Result<String, ProtestError> {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| ProtestError::UnknownPlaceholder(rest[start + 1..].to_string()))?;
+        let placeholder = &rest[start + 1..start + end];
+        match placeholder.split_once(':') {
+            None if placeholder == "seq" => rendered.push_str(&seq.to_string()),
+            Some(("seq", width)) if width.len() <= 2 && width.starts_with('0') => {
+                let width = width
+                    .parse()
+                    .map_err(|_| ProtestError::UnknownPlaceholder(placeholder.to_string()))?;
+                rendered.push_str(&format!("{:0width$}", seq, width = width));
+            }
+            None if placeholder == "round" => rendered.push_str(round),
+            None if placeholder == "name" => rendered.push_str(name),
+            _ => return Err(ProtestError::UnknownPlaceholder(placeholder.to_string())),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(sanitise_name(&rendered))
+}
+
+/// Make a name fit for a Discord channel: lower case letters, digits and single dashes
+pub fn sanitise_name(name: &str) -> String {
+    let mut sanitised = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            sanitised.push(c);
+        } else if !sanitised.is_empty() && !sanitised.ends_with('-') {
+            sanitised.push('-');
+        }
+    }
+    let mut sanitised: String = sanitised.chars().take(MAX_NAME).collect();
+    while sanitised.ends_with('-') {
+        sanitised.pop();
+    }
+    if sanitised.is_empty() {
+        "protest".to_string()
+    } else {
+        sanitised
+    }
+}
+
+/// Details which may be given when lodging a protest
+pub const FORM_FIELDS: &[&str] = &["lap", "turn", "car", "against", "video", "description"];
+
+/// How a protest should be lodged, for showing anyone who gets it wrong
+pub const FORM_EXAMPLE: &str = r#"!protest-channel t1-crash lap=12 turn=3 car=#44 against=@Driver video=https://youtu.be/abc description="Divebombed me into turn 3""#;
+
+/// The longest description we accept, leaving room in the embed for everything else
+const MAX_DESCRIPTION: usize = 2000;
+
+/// The details of an incident, given when the protest is lodged
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProtestForm {
+    pub lap: u32,
+    pub turn: Option<String>,
+    /// Number of the car involved, e.g. `#44`
+    pub car: Option<String>,
+    /// Drivers the protest is against
+    pub against: Vec<u64>,
+    /// Link to a video of the incident
+    pub video: Option<String>,
+    pub description: String,
+}
+
+impl ProtestForm {
+    /// Read the details of a protest from `key=value` fields, where values with spaces are
+    /// quoted, e.g. `lap=12 against=@Driver description="Divebombed me"`
+    pub fn parse(input: &str, protester: u64) -> Result<ProtestForm, ProtestError> {
+        let mut lap = None;
+        let mut turn = None;
+        let mut car = None;
+        let mut against = vec![];
+        let mut video = None;
+        let mut description = None;
+
+        for (key, value) in split_fields(input)? {
+            let invalid = |reason| ProtestError::InvalidField(key.clone(), value.clone(), reason);
+            match key.as_str() {
+                "lap" => {
+                    lap = Some(
+                        value
+                            .parse::<u32>()
+                            .map_err(|_| invalid("give the lap as a number"))?,
+                    )
+                }
+                "turn" if !value.is_empty() && value.len() <= 32 => turn = Some(value),
+                "turn" => return Err(invalid("give the turn number or name")),
+                "car" => {
+                    let number = value.strip_prefix('#').unwrap_or(&value);
+                    if number.is_empty()
+                        || number.len() > 3
+                        || !number.chars().all(|c| c.is_ascii_digit())
+                    {
+                        return Err(invalid("give the car number, e.g. `car=#44`"));
+                    }
+                    car = Some(format!("#{}", number));
+                }
+                "against" => {
+                    for mention in value.split(',').filter(|m| !m.is_empty()) {
+                        match parse_username(mention) {
+                            Some(id) if id == protester => {
+                                return Err(invalid("you can't protest against yourself"))
+                            }
+                            Some(id) if !against.contains(&id) => against.push(id),
+                            Some(_) => {}
+                            None => {
+                                return Err(invalid("mention the driver, e.g. `against=@Driver`"))
+                            }
+                        }
+                    }
+                }
+                "video" => match Url::parse(&value) {
+                    Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {
+                        video = Some(value)
+                    }
+                    _ => return Err(invalid("give a link starting with https://")),
+                },
+                "description" if value.trim().is_empty() => {
+                    return Err(invalid("describe what happened"))
+                }
+                "description" if value.chars().count() > MAX_DESCRIPTION => {
+                    return Err(invalid("keep the description under 2000 characters"))
+                }
+                "description" => description = Some(value),
+                _ => return Err(ProtestError::UnknownField(key)),
+            }
+        }
+
+        if against.is_empty() {
+            return Err(ProtestError::MissingField("against"));
+        }
+        Ok(ProtestForm {
+            lap: lap.ok_or(ProtestError::MissingField("lap"))?,
+            turn,
+            car,
+            against,
+            video,
+            description: description.ok_or(ProtestError::MissingField("description"))?,
+        })
+    }
+}
+
+/// Split `key=value` fields on whitespace, except within quoted values
+fn split_fields(input: &str) -> Result<Vec<(String, String)>, ProtestError> {
+    let mut fields = vec![];
+    let mut chars = input.trim().chars().peekable();
+    while chars.peek().is_some() {
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|&c| c != '=' && !c.is_whitespace()) {
+            key.push(c);
+        }
+        if chars.next() != Some('=') {
+            return Err(ProtestError::UnknownField(key));
+        }
+        let mut value = String::new();
+        match chars.peek() {
+            // Phones like to turn quotes into curly ones
+            Some('"') | Some('“') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('"') | Some('”') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(ProtestError::UnclosedQuote),
+                    }
+                }
+            }
+            _ => value.extend(chars.by_ref().take_while(|c| !c.is_whitespace())),
+        }
+        fields.push((key.to_lowercase(), value));
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    }
+    Ok(fields)
+}
+
+/// Where a protest has got to, from being lodged to the channel being closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProtestStatus {
+    Submitted,
+    UnderReview,
+    Decided,
+    Appealed,
+    Closed,
+}
+
+impl ProtestStatus {
+    pub const ALL: &'static [ProtestStatus] = &[
+        ProtestStatus::Submitted,
+        ProtestStatus::UnderReview,
+        ProtestStatus::Decided,
+        ProtestStatus::Appealed,
+        ProtestStatus::Closed,
+    ];
+
+    /// Accepts the ID or name of a status, in any case and with spaces, hyphens or underscores
+    pub fn parse(input: &str) -> Result<ProtestStatus, ProtestError> {
+        let wanted: String = input
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        ProtestStatus::ALL
+            .iter()
+            .copied()
+            .find(|status| {
+                status.id().replace('-', "") == wanted
+                    || status.name().replace(' ', "").to_lowercase() == wanted
+            })
+            .ok_or_else(|| ProtestError::UnknownStatus(input.to_string()))
+    }
+
+    /// How stewards refer to the status in commands
+    pub fn id(&self) -> &'static str {
+        match self {
+            ProtestStatus::Submitted => "submitted",
+            ProtestStatus::UnderReview => "review",
+            ProtestStatus::Decided => "decided",
+            ProtestStatus::Appealed => "appealed",
+            ProtestStatus::Closed => "closed",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProtestStatus::Submitted => "Submitted",
+            ProtestStatus::UnderReview => "Under review",
+            ProtestStatus::Decided => "Decided",
+            ProtestStatus::Appealed => "Appealed",
+            ProtestStatus::Closed => "Closed",
+        }
+    }
+
+    /// Put at the front of the channel name, so the state of every protest shows in the sidebar
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            ProtestStatus::Submitted => "new",
+            ProtestStatus::UnderReview => "review",
+            ProtestStatus::Decided => "decided",
+            ProtestStatus::Appealed => "appeal",
+            ProtestStatus::Closed => "closed",
+        }
+    }
+
+    /// Whether a protest may move from this status to `next`. A decision can be appealed and
+    /// reviewed again, but nothing comes back once it's closed.
+    pub fn can_move_to(&self, next: ProtestStatus) -> bool {
+        use ProtestStatus::*;
+        matches!(
+            (self, next),
+            (Submitted, UnderReview)
+                | (UnderReview, Decided)
+                | (Decided, Appealed)
+                | (Appealed, UnderReview)
+                | (Appealed, Decided)
+                | (Submitted | UnderReview | Decided | Appealed, Closed)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub status: ProtestStatus,
+    pub by: u64,
+    pub at: DateTime<Utc>,
+}
+
+/// A protest lodged by a driver, which has a channel of its own for the stewards to hear it in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Protest {
+    pub guild: u64,
+    pub channel: u64,
+    /// Name the protest was lodged under, without the status prefix
+    pub name: String,
+    pub protester: u64,
+    pub accused: Vec<u64>,
+    pub status: ProtestStatus,
+    pub created: DateTime<Utc>,
+    /// Every change of status after it was submitted, oldest first
+    pub changes: Vec<StatusChange>,
+    /// Details of the incident, unless the protest came in as an embed from elsewhere
+    #[serde(default)]
+    pub form: Option<ProtestForm>,
+    /// Votes the stewards have held on the outcome, oldest first
+    #[serde(default)]
+    pub votes: Vec<Vote>,
+    /// Penalties given as a result of the protest
+    #[serde(default)]
+    pub decisions: Vec<Decision>,
+    /// Stewards responsible for the protest
+    #[serde(default)]
+    pub stewards: Vec<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProtestBook {
+    /// Every protest, by its channel
+    #[serde(default)]
+    pub protests: HashMap<u64, Protest>,
+    /// Number of the last protest lodged in each guild
+    #[serde(default)]
+    pub sequences: HashMap<u64, u32>,
+    /// Where in each guild's roster to start assigning stewards
+    #[serde(default)]
+    pub steward_cursors: HashMap<u64, usize>,
+}
+
+impl ProtestBook {
+    /// Number for the next protest lodged in `guild`
+    pub fn next_sequence(&self, guild: u64) -> u32 {
+        self.sequences.get(&guild).copied().unwrap_or(0) + 1
+    }
+
+    /// Open protests each steward in `guild` is assigned to
+    pub fn steward_load(&self, guild: u64) -> HashMap<u64, usize> {
+        let mut load = HashMap::new();
+        for protest in self
+            .protests
+            .values()
+            .filter(|protest| protest.guild == guild && protest.status != ProtestStatus::Closed)
+        {
+            for &steward in &protest.stewards {
+                *load.entry(steward).or_insert(0) += 1;
+            }
+        }
+        load
+    }
+
+    /// Whether a protest which is still open in `guild` goes by `name`
+    pub fn name_taken(&self, guild: u64, name: &str) -> bool {
+        self.protests.values().any(|protest| {
+            protest.guild == guild
+                && protest.status != ProtestStatus::Closed
+                && protest.name == name
+        })
+    }
+
+    /// Name the next protest lodged in `guild` from `template` and take its number, so that
+    /// protests lodged at the same time can't share one. The name mustn't belong to an open
+    /// protest or clash with any of the guild's `channels`.
+    pub fn reserve_name(
+        &mut self,
+        guild: u64,
+        template: &str,
+        round: &str,
+        name: &str,
+        channels: &HashSet<String>,
+    ) -> Result<String, ProtestError> {
+        let seq = self.next_sequence(guild);
+        let name = render_name(template, seq, round, name)?;
+        let clashes = ProtestStatus::ALL
+            .iter()
+            .filter(|&&status| status != ProtestStatus::Closed)
+            .any(|&status| channels.contains(&channel_name(status, &name)));
+        if clashes || self.name_taken(guild, &name) {
+            return Err(ProtestError::DuplicateName(name));
+        }
+        self.sequences.insert(guild, seq);
+        Ok(name)
+    }
+}
+
+lazy_static! {
+    pub static ref PROTESTS: Store<ProtestBook> = Store::open("protests.json");
+}
+
+/// Protest settings a guild may change
+pub const SETTING_KEYS: &[&str] = &[
+    "steward_role",
+    "accused",
+    "archive_channel",
+    "archive_category",
+    "decisions_channel",
+    "ban_points",
+    "points_expiry",
+    "name_template",
+    "stewards_per_protest",
+    "assignment",
+];
+
+/// Stewards assigned to each protest unless the guild says otherwise
+pub const DEFAULT_STEWARDS_PER_PROTEST: usize = 1;
+
+/// What those hearing a protest may do in its channel
+const PARTICIPANT: Permissions = Permissions::from_bits_truncate(
+    Permissions::READ_MESSAGES.bits()
+        | Permissions::SEND_MESSAGES.bits()
+        | Permissions::READ_MESSAGE_HISTORY.bits()
+        | Permissions::ATTACH_FILES.bits()
+        | Permissions::EMBED_LINKS.bits(),
+);
+
+/// Lets a steward assigned to a protest into its channel
+pub fn steward_overwrite(steward: u64) -> PermissionOverwrite {
+    PermissionOverwrite {
+        allow: PARTICIPANT | Permissions::MANAGE_MESSAGES,
+        deny: Permissions::empty(),
+        kind: PermissionOverwriteType::Member(UserId(steward)),
+    }
+}
+
+/// What accused drivers may do in the channel for a protest against them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccusedAccess {
+    /// Accused drivers can't see the channel
+    Hidden,
+    /// Accused drivers can read the channel but not post in it
+    Read,
+    /// Accused drivers can read and reply
+    #[default]
+    Reply,
+}
+
+impl AccusedAccess {
+    pub fn parse(s: &str) -> Option<AccusedAccess> {
+        match s.to_lowercase().as_str() {
+            "hidden" | "none" => Some(AccusedAccess::Hidden),
+            "read" => Some(AccusedAccess::Read),
+            "reply" => Some(AccusedAccess::Reply),
+            _ => None,
+        }
+    }
+}
+
+/// How a guild's protest channels are set up
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProtestSettings {
+    /// Role which can see and hear every protest
+    #[serde(default)]
+    pub steward_role: Option<u64>,
+    #[serde(default)]
+    pub accused: AccusedAccess,
+    /// Channel where transcripts of closed protests are posted
+    #[serde(default)]
+    pub archive_channel: Option<u64>,
+    /// Category closed protest channels are moved to, rather than being deleted
+    #[serde(default)]
+    pub archive_category: Option<u64>,
+    /// Public channel where the stewards' decisions are published
+    #[serde(default)]
+    pub decisions_channel: Option<u64>,
+    /// Penalty points which earn a driver a race ban
+    #[serde(default)]
+    pub ban_points: Option<u32>,
+    /// How long penalty points count, or forever if None
+    #[serde(default)]
+    pub points_expiry: Option<PointsExpiry>,
+    /// Template for the names of protest channels, see `render_name`
+    #[serde(default)]
+    pub name_template: Option<String>,
+    /// Stewards assigned to each protest from the roster
+    #[serde(default)]
+    pub stewards_per_protest: Option<usize>,
+    #[serde(default)]
+    pub assignment: Assignment,
+}
+
+impl ProtestSettings {
+    /// Change settings from `key=value` fields, e.g. `steward_role=@Stewards accused=read`
+    pub fn apply(&mut self, input: &str) -> Result<(), ProtestError> {
+        for (key, value) in split_fields(input)? {
+            let invalid = |reason| ProtestError::InvalidField(key.clone(), value.clone(), reason);
+            match key.as_str() {
+                "steward_role" if value == "none" => self.steward_role = None,
+                "steward_role" => {
+                    let role = parse_role(&value).or_else(|| value.parse().ok());
+                    self.steward_role =
+                        Some(role.ok_or_else(|| invalid("mention the role, or give `none`"))?);
+                }
+                "accused" => {
+                    self.accused = AccusedAccess::parse(&value)
+                        .ok_or_else(|| invalid("give one of hidden, read or reply"))?
+                }
+                "archive_channel" | "archive_category" | "decisions_channel" => {
+                    let channel = if value == "none" {
+                        None
+                    } else {
+                        let id = parse_channel(&value).or_else(|| value.parse().ok());
+                        Some(id.ok_or_else(|| {
+                            invalid("mention the channel, or give its ID or `none`")
+                        })?)
+                    };
+                    match key.as_str() {
+                        "archive_channel" => self.archive_channel = channel,
+                        "archive_category" => self.archive_category = channel,
+                        _ => self.decisions_channel = channel,
+                    }
+                }
+                "ban_points" if value == "none" => self.ban_points = None,
+                "ban_points" => {
+                    self.ban_points = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&points| points > 0)
+                            .ok_or_else(|| invalid("give the points for a ban, or `none`"))?,
+                    )
+                }
+                "points_expiry" if value == "none" => self.points_expiry = None,
+                "points_expiry" => {
+                    self.points_expiry = Some(PointsExpiry::parse(&value).ok_or_else(|| {
+                        invalid(
+                            "give up to 3650 days or a number of events, e.g. `365d` or `4events`",
+                        )
+                    })?)
+                }
+                "stewards_per_protest" => {
+                    self.stewards_per_protest = Some(
+                        value
+                            .parse()
+                            .map_err(|_| invalid("give the number of stewards for each protest"))?,
+                    )
+                }
+                "assignment" => {
+                    self.assignment = Assignment::parse(&value)
+                        .ok_or_else(|| invalid("give round-robin or least-loaded"))?
+                }
+                "name_template" if value == "none" => self.name_template = None,
+                "name_template" => {
+                    // Try the template out so a bad one is caught now rather than at the next protest
+                    render_name(&value, 1, "round", "name")?;
+                    self.name_template = Some(value);
+                }
+                _ => return Err(ProtestError::UnknownSetting(key)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Permissions for the channel of a protest, so that only those involved, the stewards and
+    /// the bot itself can see it
+    pub fn channel_overwrites(
+        &self,
+        guild: u64,
+        bot: u64,
+        protester: u64,
+        accused: &[u64],
+        stewards: &[u64],
+    ) -> Vec<PermissionOverwrite> {
+        let participant = PARTICIPANT;
+        let member = |id, allow, deny| PermissionOverwrite {
+            allow,
+            deny,
+            kind: PermissionOverwriteType::Member(UserId(id)),
+        };
+
+        // The everyone role shares its ID with the guild
+        let mut overwrites = vec![
+            PermissionOverwrite {
+                allow: Permissions::empty(),
+                deny: Permissions::READ_MESSAGES,
+                kind: PermissionOverwriteType::Role(RoleId(guild)),
+            },
+            member(
+                bot,
+                participant | Permissions::MANAGE_MESSAGES,
+                Permissions::empty(),
+            ),
+            member(protester, participant, Permissions::empty()),
+        ];
+        if let Some(role) = self.steward_role {
+            overwrites.push(PermissionOverwrite {
+                allow: participant | Permissions::MANAGE_MESSAGES,
+                deny: Permissions::empty(),
+                kind: PermissionOverwriteType::Role(RoleId(role)),
+            });
+        }
+        let (allow, deny) = match self.accused {
+            AccusedAccess::Hidden => (Permissions::empty(), Permissions::READ_MESSAGES),
+            AccusedAccess::Read => (
+                Permissions::READ_MESSAGES | Permissions::READ_MESSAGE_HISTORY,
+                Permissions::SEND_MESSAGES,
+            ),
+            AccusedAccess::Reply => (participant, Permissions::empty()),
+        };
+        for &id in accused.iter().filter(|&&id| id != protester && id != bot) {
+            overwrites.push(member(id, allow, deny));
+        }
+        overwrites.extend(stewards.iter().map(|&id| steward_overwrite(id)));
+        overwrites
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "protests are named `{}` and heard by {}, with {} from the roster assigned to each by {}. Accused drivers may {}. Decisions {}. Transcripts of closed protests {}, and their channels are {}. Penalty points {} and {}.",
+            self.name_template
+                .as_deref()
+                .unwrap_or(DEFAULT_NAME_TEMPLATE),
+            self.steward_role
+                .map(|role| format!("<@&{}>", role))
+                .unwrap_or_else(|| "server managers".to_string()),
+            self.stewards_per_protest
+                .unwrap_or(DEFAULT_STEWARDS_PER_PROTEST),
+            self.assignment.id(),
+            match self.accused {
+                AccusedAccess::Hidden => "not see them",
+                AccusedAccess::Read => "read but not reply",
+                AccusedAccess::Reply => "read and reply",
+            },
+            self.decisions_channel
+                .map(|channel| format!("are published in <#{}>", channel))
+                .unwrap_or_else(|| "are only given in the protest's channel".to_string()),
+            self.archive_channel
+                .map(|channel| format!("are posted in <#{}>", channel))
+                .unwrap_or_else(|| "are only kept by the bot".to_string()),
+            if self.archive_category.is_some() {
+                "archived"
+            } else {
+                "deleted"
+            },
+            self.points_expiry
+                .map(|expiry| format!("count for {}", expiry))
+                .unwrap_or_else(|| "never expire".to_string()),
+            self.ban_points
+                .map(|points| format!("{} of them earn a race ban", points))
+                .unwrap_or_else(|| "never earn a race ban".to_string())
+        )
+    }
+}
+
+/// The channel name for a protest called `name` in `status`
+pub fn channel_name(status: ProtestStatus, name: &str) -> String {
+    format!("{}-{}", status.prefix(), name)
+}
+
+/// The channel topic for a protest in `status`
+pub fn topic(status: ProtestStatus) -> String {
+    format!("Protest status: {}", status.name())
+}
+
+impl Protest {
+    pub fn new(
+        guild: u64,
+        channel: u64,
+        name: String,
+        protester: u64,
+        accused: Vec<u64>,
+        now: DateTime<Utc>,
+    ) -> Protest {
+        Protest {
+            guild,
+            channel,
+            name,
+            protester,
+            accused,
+            status: ProtestStatus::Submitted,
+            created: now,
+            changes: vec![],
+            form: None,
+            votes: vec![],
+            decisions: vec![],
+            stewards: vec![],
+        }
+    }
+
+    pub fn channel_name(&self) -> String {
+        channel_name(self.status, &self.name)
+    }
+
+    pub fn topic(&self) -> String {
+        topic(self.status)
+    }
+
+    /// The vote the stewards are holding, if there is one
+    pub fn open_vote(&mut self) -> Option<&mut Vote> {
+        self.votes.last_mut().filter(|vote| vote.is_open())
+    }
+
+    /// Move the protest on to `status`, if that's a move it can make
+    pub fn set_status(
+        &mut self,
+        status: ProtestStatus,
+        by: u64,
+        now: DateTime<Utc>,
+    ) -> Result<(), ProtestError> {
+        if !self.status.can_move_to(status) {
+            return Err(ProtestError::Transition(self.status, status));
+        }
+        self.status = status;
+        self.changes.push(StatusChange {
+            status,
+            by,
+            at: now,
+        });
+        Ok(())
+    }
+}
+
+/// The protest heard in `channel`, if there is one
+pub fn get(channel: u64) -> Option<Protest> {
+    PROTESTS.read(|book| book.protests.get(&channel).cloned())
+}
+
+/// Change the protest in `channel` where it's kept, so nothing saved to it meanwhile is lost.
+/// Returns None if there's no protest in the channel.
+pub fn update<R>(channel: u64, f: impl FnOnce(&mut Protest) -> R) -> Result<Option<R>, StoreError> {
+    PROTESTS.update(|book| book.protests.get_mut(&channel).map(f))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn protests_move_through_their_lifecycle() {
+        assert_eq!(
+            Ok(ProtestStatus::UnderReview),
+            ProtestStatus::parse("Under Review")
+        );
+        assert_eq!(
+            Ok(ProtestStatus::UnderReview),
+            ProtestStatus::parse("under-review")
+        );
+        assert_eq!(
+            Ok(ProtestStatus::UnderReview),
+            ProtestStatus::parse("review")
+        );
+        assert!(ProtestStatus::parse("dismissed").is_err());
+
+        let mut protest = Protest::new(1, 2, "t1-crash".to_string(), 3, vec![4], Utc::now());
+        assert_eq!("new-t1-crash", protest.channel_name());
+        assert_eq!(
+            Err(ProtestError::Transition(
+                ProtestStatus::Submitted,
+                ProtestStatus::Decided
+            )),
+            protest.set_status(ProtestStatus::Decided, 5, Utc::now())
+        );
+
+        for status in [
+            ProtestStatus::UnderReview,
+            ProtestStatus::Decided,
+            ProtestStatus::Appealed,
+            ProtestStatus::Decided,
+            ProtestStatus::Closed,
+        ] {
+            protest.set_status(status, 5, Utc::now()).unwrap();
+        }
+        assert_eq!("closed-t1-crash", protest.channel_name());
+        assert_eq!(5, protest.changes.len());
+        assert!(protest
+            .set_status(ProtestStatus::UnderReview, 5, Utc::now())
+            .is_err());
+    }
+
+    #[test]
+    fn reads_protest_forms() {
+        let form = ProtestForm::parse(
+            r#"lap=12 turn=3 car=#44 against=<@!200>,<@300> video=https://youtu.be/abc description="Divebombed me into turn 3""#,
+            100,
+        )
+        .unwrap();
+        assert_eq!(12, form.lap);
+        assert_eq!(Some("3".to_string()), form.turn);
+        assert_eq!(Some("#44".to_string()), form.car);
+        assert_eq!(vec![200, 300], form.against);
+        assert_eq!("Divebombed me into turn 3", form.description);
+
+        let form = ProtestForm::parse("against=<@200> lap=3 car=7 description=“Pushed wide”", 100);
+        assert_eq!(Some("#7".to_string()), form.unwrap().car);
+
+        let parse = |input| ProtestForm::parse(input, 100).unwrap_err();
+        assert_eq!(
+            ProtestError::MissingField("description"),
+            parse("lap=12 against=<@200>")
+        );
+        assert_eq!(
+            ProtestError::MissingField("against"),
+            parse("lap=12 description=Crash")
+        );
+        assert!(matches!(
+            parse("lap=12 against=Driver description=Crash"),
+            ProtestError::InvalidField(..)
+        ));
+        assert!(matches!(
+            parse("lap=12 against=<@100> description=Crash"),
+            ProtestError::InvalidField(..)
+        ));
+        assert!(matches!(
+            parse("lap=12 against=<@200> video=youtube description=Crash"),
+            ProtestError::InvalidField(..)
+        ));
+        assert_eq!(
+            ProtestError::UnknownField("Crash".to_string()),
+            parse("lap=12 against=<@200> Crash")
+        );
+        assert_eq!(
+            ProtestError::UnclosedQuote,
+            parse(r#"lap=12 against=<@200> description="Crash"#)
+        );
+    }
+
+    #[test]
+    fn protest_channels_are_private() {
+        let mut settings = ProtestSettings::default();
+        settings.apply("steward_role=<@&50> accused=read").unwrap();
+        assert_eq!(Some(50), settings.steward_role);
+        assert_eq!(AccusedAccess::Read, settings.accused);
+        settings
+            .apply("archive_channel=<#60> archive_category=70")
+            .unwrap();
+        assert_eq!(Some(60), settings.archive_channel);
+        assert_eq!(Some(70), settings.archive_category);
+        settings
+            .apply("ban_points=12 points_expiry=4events")
+            .unwrap();
+        assert_eq!(Some(12), settings.ban_points);
+        assert_eq!(Some(PointsExpiry::Events(4)), settings.points_expiry);
+        assert_eq!(
+            ProtestError::UnknownSetting("colour".to_string()),
+            settings.apply("colour=red").unwrap_err()
+        );
+
+        let overwrites = settings.channel_overwrites(1, 2, 100, &[200], &[300]);
+        let find = |kind: PermissionOverwriteType| {
+            overwrites
+                .iter()
+                .find(|o| o.kind == kind)
+                .map(|o| (o.allow, o.deny))
+                .unwrap()
+        };
+        let everyone = find(PermissionOverwriteType::Role(RoleId(1)));
+        assert!(everyone.1.contains(Permissions::READ_MESSAGES));
+        let stewards = find(PermissionOverwriteType::Role(RoleId(50)));
+        assert!(stewards.0.contains(Permissions::SEND_MESSAGES));
+        let protester = find(PermissionOverwriteType::Member(UserId(100)));
+        assert!(protester.0.contains(Permissions::SEND_MESSAGES));
+        let accused = find(PermissionOverwriteType::Member(UserId(200)));
+        assert!(accused.0.contains(Permissions::READ_MESSAGES));
+        assert!(accused.1.contains(Permissions::SEND_MESSAGES));
+        let steward = find(PermissionOverwriteType::Member(UserId(300)));
+        assert!(steward.0.contains(Permissions::MANAGE_MESSAGES));
+
+        settings.apply("accused=hidden").unwrap();
+        let overwrites = settings.channel_overwrites(1, 2, 100, &[200], &[]);
+        assert!(overwrites
+            .iter()
+            .any(|o| o.kind == PermissionOverwriteType::Member(UserId(200))
+                && o.deny.contains(Permissions::READ_MESSAGES)));
+    }
+
+    #[test]
+    fn names_protests_from_the_template() {
+        assert_eq!(
+            "protest-0042-round-3-t1-crash",
+            render_name(
+                "protest-{seq:04}-{round}-{name}",
+                42,
+                "Round 3",
+                "T1 crash!"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            "7-t1-crash",
+            render_name("{seq}-{name}", 7, "", "t1_crash").unwrap()
+        );
+        assert_eq!(
+            "protest-12",
+            render_name("protest-{round}-{seq}", 12, "", "").unwrap()
+        );
+        assert_eq!(
+            ProtestError::UnknownPlaceholder("driver".to_string()),
+            render_name("{driver}", 1, "", "").unwrap_err()
+        );
+        assert_eq!("protest", sanitise_name("!!!"));
+        assert_eq!(90, sanitise_name(&"a".repeat(200)).len());
+
+        let mut book = ProtestBook::default();
+        assert_eq!(1, book.next_sequence(1));
+        book.sequences.insert(1, 4);
+        assert_eq!(5, book.next_sequence(1));
+        let mut protest = Protest::new(1, 2, "t1-crash".to_string(), 100, vec![], Utc::now());
+        book.protests.insert(2, protest.clone());
+        assert!(book.name_taken(1, "t1-crash"));
+        assert!(!book.name_taken(3, "t1-crash"));
+        protest.status = ProtestStatus::Closed;
+        book.protests.insert(2, protest);
+        assert!(!book.name_taken(1, "t1-crash"));
+
+        let mut channels = HashSet::new();
+        assert_eq!(
+            Ok("protest-5".to_string()),
+            book.reserve_name(1, "protest-{seq}", "", "", &channels)
+        );
+        assert_eq!(6, book.next_sequence(1));
+        channels.insert("review-protest-6".to_string());
+        assert_eq!(
+            Err(ProtestError::DuplicateName("protest-6".to_string())),
+            book.reserve_name(1, "protest-{seq}", "", "", &channels)
+        );
+        // A failed name doesn't use up the number
+        assert_eq!(6, book.next_sequence(1));
+    }
+}
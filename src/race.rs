@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use stewart_strat::args::parse_mmss;
+use stewart_strat::live::RaceState;
+use stewart_strat::{StrategyInner, StrategyInput};
+
+use crate::store::Store;
+
+/// How many laps before each stop we remind the channel, furthest first. The first reminder
+/// also goes to the driver taking over.
+pub const REMINDER_LAPS: &[u32] = &[3, 1];
+
+/// How much each lap of telemetry counts towards the live lap time and consumption
+const LIVE_WEIGHT: f64 = 0.25;
+
+/// Laps further than this from the live average, by proportion, are left out of it, as they're
+/// likely to be under a safety car or in the pits
+const LIVE_OUTLIER: f64 = 0.2;
+
+const TOKEN_LENGTH: usize = 32;
+
+/// A race being run to a strategy, with its own clock so stops can be called out as they come
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Race {
+    pub channel: u64,
+    /// The saved strategy the plan came from, if it was started from one
+    pub strategy_id: Option<String>,
+    pub started: DateTime<Utc>,
+    /// When the clock was paused, if it's paused now
+    pub paused_at: Option<DateTime<Utc>>,
+    /// Seconds the clock has spent paused, not counting a pause still going
+    pub paused_secs: f64,
+    /// Seconds the clock has been moved on by, or back by if negative
+    pub adjust_secs: f64,
+    pub race_secs: f64,
+    /// Drivers in the order they take the car, starting with the first stint
+    pub drivers: Vec<u64>,
+    pub stops: Vec<RaceStop>,
+    /// Who started the race, and so may ask for its telemetry token
+    #[serde(default)]
+    pub started_by: u64,
+    /// The input to the plan, kept up to date with the lap times and consumption from telemetry
+    #[serde(default)]
+    pub input: Option<StrategyInput>,
+    /// Secret a telemetry relay must present to report on the race
+    #[serde(default)]
+    pub telemetry_token: String,
+    /// The latest report from telemetry
+    #[serde(default)]
+    pub telemetry: Option<Telemetry>,
+    /// Stops telemetry has seen the car make, from the fuel going up
+    #[serde(default)]
+    pub stops_made: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RaceStop {
+    pub lap: u32,
+    /// Race time the car is due in
+    pub due_secs: f64,
+    /// Lap time over the stint leading up to the stop, for counting down laps
+    pub laptime_secs: f64,
+    /// What the crew need to do, e.g. "add 72 L, fit Wets"
+    pub service: String,
+    /// Driver taking over at the stop
+    pub driver: Option<u64>,
+    /// Reminders already sent, by how many laps ahead they were
+    pub reminded: Vec<u32>,
+}
+
+/// A report from a telemetry relay, sent at the end of each lap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Telemetry {
+    /// Laps completed
+    pub lap: u32,
+    /// Fuel on board, in the units of the plan
+    pub fuel: f64,
+    /// Time of the lap just completed, in seconds
+    #[serde(default)]
+    pub lap_time: Option<f64>,
+    /// Race time elapsed in seconds, which the race clock is set to if given
+    #[serde(default)]
+    pub race_time: Option<f64>,
+}
+
+/// A stop coming up which the channel should hear about
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reminder {
+    /// Index of the stop in the plan
+    pub stop: usize,
+    pub laps: u32,
+}
+
+lazy_static! {
+    pub static ref RACES: Store<HashMap<u64, Race>> = Store::open("races.json");
+}
+
+impl Race {
+    /// Start the clock on `strategy` now, in `channel`
+    pub fn new(
+        channel: u64,
+        input: &StrategyInput,
+        strategy: &StrategyInner,
+        drivers: Vec<u64>,
+        started_by: u64,
+        now: DateTime<Utc>,
+    ) -> Race {
+        let mut race = Race {
+            channel,
+            strategy_id: None,
+            started: now,
+            paused_at: None,
+            paused_secs: 0.0,
+            adjust_secs: 0.0,
+            race_secs: input.race_duration.as_secs_f64(),
+            drivers,
+            stops: vec![],
+            started_by,
+            input: Some(input.clone()),
+            telemetry_token: rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(TOKEN_LENGTH)
+                .map(char::from)
+                .collect(),
+            telemetry: None,
+            stops_made: 0,
+        };
+        race.stops = race.plan_stops(strategy, 0.0);
+        race
+    }
+
+    /// The stops in `strategy`, which starts `from_secs` into the race
+    fn plan_stops(&self, strategy: &StrategyInner, from_secs: f64) -> Vec<RaceStop> {
+        let pit_secs = self
+            .input
+            .as_ref()
+            .and_then(|input| input.pit_stop_duration)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let mut elapsed = from_secs;
+        strategy
+            .stops
+            .iter()
+            .zip(&strategy.stints)
+            .enumerate()
+            .map(|(i, (stop, stint))| {
+                elapsed += stint.duration.as_secs_f64();
+                let due_secs = elapsed;
+                elapsed += pit_secs;
+
+                let mut service = format!("add {}", strategy.fuel_units.format(stop.fuel_to_add));
+                if let Some(tyres) = stop.tyres {
+                    service.push_str(&format!(", fit {}", tyres.name()));
+                }
+                let next = self.stops_made as usize + i + 1;
+                RaceStop {
+                    lap: stop.lap,
+                    due_secs,
+                    laptime_secs: stint.duration.as_secs_f64() / stint.laps.max(1) as f64,
+                    service,
+                    driver: (!self.drivers.is_empty())
+                        .then(|| self.drivers[next % self.drivers.len()]),
+                    reminded: vec![],
+                }
+            })
+            .collect()
+    }
+
+    /// Whether `token` is the one telemetry for this race must present
+    pub fn telemetry_authorised(&self, token: &str) -> bool {
+        // Compare every byte, so the time taken doesn't give away how much of it was right
+        let expected = self.telemetry_token.as_bytes();
+        !expected.is_empty()
+            && expected.len() == token.len()
+            && expected
+                .iter()
+                .zip(token.as_bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+
+    /// Take in a telemetry report, updating the live lap time and consumption and re-planning
+    /// the rest of the race. Returns the new plan along with the lap the car was due in before,
+    /// if the next stop has moved. Reports with times or fuel we can't use are ignored.
+    pub fn record(
+        &mut self,
+        report: Telemetry,
+        now: DateTime<Utc>,
+    ) -> Option<(Option<u32>, StrategyInner)> {
+        if self
+            .telemetry
+            .as_ref()
+            .is_some_and(|last| report.lap <= last.lap)
+        {
+            return None;
+        }
+        let time_ok = |secs: f64| secs.is_finite() && secs >= 0.0 && secs <= self.race_secs;
+        if !report.fuel.is_finite()
+            || !report.race_time.is_none_or(time_ok)
+            || !report
+                .lap_time
+                .is_none_or(|secs| secs > 0.0 && time_ok(secs))
+        {
+            return None;
+        }
+        if let Some(race_time) = report.race_time {
+            self.adjust_secs += race_time - self.race_time(now);
+        }
+
+        let race_time = self.race_time(now);
+        let input = self.input.as_mut()?;
+        let mut pitted = false;
+        if let Some(last) = &self.telemetry {
+            if report.fuel > last.fuel {
+                pitted = true;
+                self.stops_made = self.stops_made.saturating_add(1);
+            } else if report.lap == last.lap + 1 {
+                input.fuel_per_lap = blend(input.fuel_per_lap, last.fuel - report.fuel);
+            }
+        }
+        if let Some(lap_time) = report.lap_time.filter(|_| !pitted) {
+            let average = blend(input.avg_laptime.as_secs_f64(), lap_time);
+            input.avg_laptime = std::time::Duration::from_secs_f64(average);
+        }
+
+        let plan = input.replan(&RaceState {
+            lap: report.lap,
+            elapsed: std::time::Duration::from_secs_f64(race_time),
+            fuel: report.fuel,
+            stops_made: self.stops_made,
+        });
+        self.telemetry = Some(report);
+        let plan = plan?;
+
+        let was = self.next_stop(now).map(|(_, stop)| stop.lap);
+        if plan.stops.first().map(|stop| stop.lap) == was {
+            return None;
+        }
+        self.stops = self.plan_stops(&plan, race_time);
+        Some((was, plan))
+    }
+
+    /// Seconds of racing so far, leaving out time paused
+    pub fn race_time(&self, now: DateTime<Utc>) -> f64 {
+        let until = self.paused_at.unwrap_or(now);
+        let wall = (until - self.started).num_milliseconds() as f64 / 1000.0;
+        (wall - self.paused_secs + self.adjust_secs).max(0.0)
+    }
+
+    pub fn finished(&self, now: DateTime<Utc>) -> bool {
+        self.race_time(now) >= self.race_secs
+    }
+
+    /// Stop the clock, returning false if it's already stopped
+    pub fn pause(&mut self, now: DateTime<Utc>) -> bool {
+        if self.paused_at.is_some() {
+            return false;
+        }
+        self.paused_at = Some(now);
+        true
+    }
+
+    /// Restart the clock, returning false if it's already running
+    pub fn resume(&mut self, now: DateTime<Utc>) -> bool {
+        match self.paused_at.take() {
+            Some(paused_at) => {
+                self.paused_secs += (now - paused_at).num_milliseconds() as f64 / 1000.0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Move the clock on by `secs`, or back if negative, to match the race
+    pub fn adjust(&mut self, secs: f64) {
+        self.adjust_secs += secs;
+    }
+
+    /// The next stop still to come, with its index in the plan
+    pub fn next_stop(&self, now: DateTime<Utc>) -> Option<(usize, &RaceStop)> {
+        let race_time = self.race_time(now);
+        self.stops
+            .iter()
+            .enumerate()
+            .find(|(_, stop)| stop.due_secs > race_time)
+    }
+
+    /// Laps left until the car is due in for `stop`, counting the one it's on
+    pub fn laps_until(&self, stop: &RaceStop, now: DateTime<Utc>) -> u32 {
+        let remaining = (stop.due_secs - self.race_time(now)).max(0.0);
+        (remaining / stop.laptime_secs).ceil() as u32
+    }
+
+    /// Reminders which have come due and not been sent. Only the nearest for each stop is
+    /// given, so a clock moved on doesn't send a stale one after it.
+    pub fn reminders(&self, now: DateTime<Utc>) -> Vec<Reminder> {
+        if self.paused_at.is_some() {
+            return vec![];
+        }
+        self.stops
+            .iter()
+            .enumerate()
+            .filter_map(|(i, stop)| {
+                self.due_reminders(stop, now)
+                    .min()
+                    .map(|laps| Reminder { stop: i, laps })
+            })
+            .collect()
+    }
+
+    /// Record every reminder that has come due as sent
+    pub fn remind(&mut self, now: DateTime<Utc>) {
+        if self.paused_at.is_some() {
+            return;
+        }
+        let due: Vec<Vec<u32>> = self
+            .stops
+            .iter()
+            .map(|stop| self.due_reminders(stop, now).collect())
+            .collect();
+        for (stop, due) in self.stops.iter_mut().zip(due) {
+            stop.reminded.extend(due);
+        }
+    }
+
+    fn due_reminders<'a>(
+        &self,
+        stop: &'a RaceStop,
+        now: DateTime<Utc>,
+    ) -> impl Iterator<Item = u32> + 'a {
+        let remaining = stop.due_secs - self.race_time(now);
+        REMINDER_LAPS.iter().copied().filter(move |&laps| {
+            remaining > 0.0
+                && remaining <= laps as f64 * stop.laptime_secs
+                && !stop.reminded.contains(&laps)
+        })
+    }
+}
+
+/// Fold a lap into a live average, unless it's too far off to be a normal racing lap
+fn blend(average: f64, lap: f64) -> f64 {
+    if (lap - average).abs() > average * LIVE_OUTLIER {
+        average
+    } else {
+        average + (lap - average) * LIVE_WEIGHT
+    }
+}
+
+/// Parse a change to the race clock in seconds or MM:SS, with a sign to move it back,
+/// e.g. `+45` or `-1:30`
+pub fn parse_adjustment(input: &str) -> Option<f64> {
+    let (sign, value) = match input.strip_prefix('-') {
+        Some(value) => (-1.0, value),
+        None => (1.0, input.strip_prefix('+').unwrap_or(input)),
+    };
+    let secs = if value.contains(':') {
+        parse_mmss(value)?.as_secs_f64()
+    } else {
+        value.parse::<u32>().ok()? as f64
+    };
+    Some(sign * secs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    fn race(now: DateTime<Utc>) -> Race {
+        let input = StrategyInput::builder(
+            std::time::Duration::from_secs(2 * 3600),
+            std::time::Duration::from_secs(120),
+            3.0,
+            90.0,
+        )
+        .pit_stop_duration(std::time::Duration::from_secs(60))
+        .build()
+        .unwrap();
+        let strategy = input.calculate().remove(0);
+        Race::new(1, &input, strategy.inner(), vec![10, 20], 5, now)
+    }
+
+    #[test]
+    fn reminds_before_each_stop() {
+        let start = Utc::now();
+        let mut race = race(start);
+        // 30 laps of 2:00 on a tank, so the first stop is due an hour in
+        assert_eq!(3600.0, race.stops[0].due_secs);
+        assert_eq!(Some(20), race.stops[0].driver);
+
+        let early = start + Duration::seconds(3000);
+        assert!(race.reminders(early).is_empty());
+        assert_eq!(5, race.laps_until(&race.stops[0], early));
+
+        let three_laps = start + Duration::seconds(3600 - 350);
+        assert_eq!(
+            vec![Reminder { stop: 0, laps: 3 }],
+            race.reminders(three_laps)
+        );
+        race.remind(three_laps);
+        assert!(race.reminders(three_laps).is_empty());
+
+        // Jumping straight to the last lap sends only the last reminder
+        let mut skipped = race.clone();
+        skipped.stops[0].reminded.clear();
+        let last_lap = start + Duration::seconds(3600 - 60);
+        assert_eq!(
+            vec![Reminder { stop: 0, laps: 1 }],
+            skipped.reminders(last_lap)
+        );
+    }
+
+    #[test]
+    fn pausing_and_adjusting_move_the_clock() {
+        let start = Utc::now();
+        let mut race = race(start);
+
+        let paused = start + Duration::seconds(600);
+        assert!(race.pause(paused));
+        assert!(!race.pause(paused));
+        assert_eq!(600.0, race.race_time(paused + Duration::seconds(300)));
+        assert!(race.reminders(start + Duration::seconds(3590)).is_empty());
+
+        assert!(race.resume(paused + Duration::seconds(300)));
+        assert_eq!(700.0, race.race_time(start + Duration::seconds(1000)));
+
+        race.adjust(parse_adjustment("-1:30").unwrap());
+        assert_eq!(610.0, race.race_time(start + Duration::seconds(1000)));
+        assert_eq!(Some(45.0), parse_adjustment("+45"));
+        assert_eq!(None, parse_adjustment("soon"));
+    }
+
+    #[test]
+    fn telemetry_moves_the_next_stop() {
+        let start = Utc::now();
+        let mut race = race(start);
+        let token = race.telemetry_token.clone();
+        assert!(race.telemetry_authorised(&token));
+        assert!(!race.telemetry_authorised(&token[1..]));
+        assert!(!race.telemetry_authorised(""));
+
+        let report = |lap, fuel| Telemetry {
+            lap,
+            fuel,
+            lap_time: Some(120.0),
+            race_time: Some(lap as f64 * 120.0),
+        };
+
+        // On target, so the plan stands
+        assert!(race.record(report(9, 63.0), start).is_none());
+
+        // Using more than planned brings the stop forward
+        let (was, plan) = race.record(report(10, 59.5), start).unwrap();
+        assert_eq!(Some(30), was);
+        assert_eq!(29, plan.stops[0].lap);
+        assert_eq!(29, race.stops[0].lap);
+        assert!(race.input.as_ref().unwrap().fuel_per_lap > 3.0);
+
+        // Old or repeated reports are ignored
+        assert!(race.record(report(10, 40.0), start).is_none());
+
+        // As are reports with times beyond the race, which leave the clock alone
+        let adjust = race.adjust_secs;
+        for bad in &[1e20, f64::NAN, f64::INFINITY, -1.0] {
+            let mut report = report(11, 56.0);
+            report.race_time = Some(*bad);
+            assert!(race.record(report.clone(), start).is_none());
+            report.race_time = None;
+            report.lap_time = Some(*bad);
+            assert!(race.record(report, start).is_none());
+        }
+        assert_eq!(adjust, race.adjust_secs);
+        assert_eq!(10, race.telemetry.as_ref().unwrap().lap);
+    }
+}
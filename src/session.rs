@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::store::Store;
+use crate::vote::parse_period;
+
+/// How long after a race protests may be lodged, unless the session says otherwise
+pub const DEFAULT_PROTEST_WINDOW_MINS: i64 = 120;
+
+/// Longest a session may take protests for
+pub const MAX_PROTEST_WINDOW_MINS: i64 = 7 * 24 * 60;
+
+lazy_static! {
+    /// Each guild's race sessions, by guild
+    pub static ref SESSIONS: Store<HashMap<u64, GuildSessions>> = Store::open("sessions.json");
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SessionError {
+    #[error("`{0}` isn't a time, give `now` or a UTC time such as `2021-03-14 20:00`")]
+    InvalidTime(String),
+    #[error("`{0}` isn't a protest window, give a time of up to a week, such as 30m, 2h or 1d")]
+    InvalidWindow(String),
+    #[error("there's already a session called `{0}`")]
+    Duplicate(String),
+    #[error("there's no session called `{0}`")]
+    Unknown(String),
+    #[error("protests for {} had to be lodged by {}", .0, .1.format("%Y-%m-%d %H:%M UTC"))]
+    Late(String, DateTime<Utc>),
+}
+
+/// A race session, after which protests may be lodged for a while
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub name: String,
+    pub ended: DateTime<Utc>,
+    /// Minutes after the end in which protests may be lodged
+    pub window_mins: i64,
+}
+
+impl Session {
+    /// Read a session from its name, end time and any `window=<time>`
+    pub fn parse(
+        name: &str,
+        ended: &str,
+        window: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<Session, SessionError> {
+        let ended =
+            parse_time(ended, now).ok_or_else(|| SessionError::InvalidTime(ended.to_string()))?;
+        let window_mins = match window {
+            Some(window) => parse_period(window)
+                .map(|window| window.num_minutes())
+                .filter(|&mins| mins <= MAX_PROTEST_WINDOW_MINS)
+                .ok_or_else(|| SessionError::InvalidWindow(window.to_string()))?,
+            None => DEFAULT_PROTEST_WINDOW_MINS,
+        };
+        Ok(Session {
+            name: name.to_string(),
+            ended,
+            window_mins,
+        })
+    }
+
+    /// When protests for the session must be lodged by
+    pub fn deadline(&self) -> DateTime<Utc> {
+        let window = Duration::minutes(self.window_mins.clamp(0, MAX_PROTEST_WINDOW_MINS));
+        self.ended
+            .checked_add_signed(window)
+            .unwrap_or(chrono::MAX_DATETIME)
+    }
+}
+
+/// Read `now`, an RFC 3339 time or `YYYY-MM-DD HH:MM` in UTC
+fn parse_time(s: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if s.eq_ignore_ascii_case("now") {
+        return Some(now);
+    }
+    s.parse::<DateTime<Utc>>().ok().or_else(|| {
+        ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+            .map(|time| DateTime::from_utc(time, Utc))
+    })
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuildSessions {
+    #[serde(default)]
+    pub sessions: Vec<Session>,
+    /// Drivers an admin has let lodge one protest after the deadline
+    #[serde(default)]
+    pub late_allowed: Vec<u64>,
+}
+
+impl GuildSessions {
+    pub fn add(&mut self, session: Session) -> Result<(), SessionError> {
+        if self.get(&session.name).is_some() {
+            return Err(SessionError::Duplicate(session.name));
+        }
+        self.sessions.push(session);
+        self.sessions.sort_by_key(|session| session.ended);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Session, SessionError> {
+        let index = self
+            .sessions
+            .iter()
+            .position(|session| session.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| SessionError::Unknown(name.to_string()))?;
+        Ok(self.sessions.remove(index))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Session> {
+        self.sessions
+            .iter()
+            .find(|session| session.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The last session to have ended by `now`
+    pub fn latest(&self, now: DateTime<Utc>) -> Option<&Session> {
+        self.sessions
+            .iter()
+            .rev()
+            .find(|session| session.ended <= now)
+    }
+
+    /// Check a protest may be lodged now, against the last session to have ended. Guilds
+    /// without any sessions take protests at any time.
+    pub fn check_deadline(&self, now: DateTime<Utc>) -> Result<(), SessionError> {
+        match self.latest(now) {
+            Some(session) if now > session.deadline() => {
+                Err(SessionError::Late(session.name.clone(), session.deadline()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn has_late_allowance(&self, driver: u64) -> bool {
+        self.late_allowed.contains(&driver)
+    }
+
+    /// Use up a driver's permission to lodge a late protest, if they have one
+    pub fn take_late_allowance(&mut self, driver: u64) -> bool {
+        let allowed = self.late_allowed.contains(&driver);
+        self.late_allowed.retain(|&id| id != driver);
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn protests_close_after_the_window() {
+        let now = "2021-03-14T20:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let mut sessions = GuildSessions::default();
+        assert_eq!(Ok(()), sessions.check_deadline(now));
+
+        sessions
+            .add(Session::parse("round-1", "2021-03-14 18:30", None, now).unwrap())
+            .unwrap();
+        sessions
+            .add(Session::parse("round-2", "2021-03-21 18:30", Some("1h"), now).unwrap())
+            .unwrap();
+        assert_eq!(
+            Err(SessionError::Duplicate("Round-1".to_string())),
+            sessions.add(Session::parse("Round-1", "now", None, now).unwrap())
+        );
+        assert_eq!(Ok(()), sessions.check_deadline(now));
+
+        let late = now + Duration::minutes(31);
+        assert_eq!(
+            Err(SessionError::Late(
+                "round-1".to_string(),
+                now + Duration::minutes(30)
+            )),
+            sessions.check_deadline(late)
+        );
+
+        let after_round_2 = "2021-03-21T19:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(Ok(()), sessions.check_deadline(after_round_2));
+
+        assert!(matches!(
+            Session::parse("round-3", "next week", None, now),
+            Err(SessionError::InvalidTime(..))
+        ));
+        for window in &["8d", "99999999999d"] {
+            assert_eq!(
+                Err(SessionError::InvalidWindow(window.to_string())),
+                Session::parse("round-3", "now", Some(window), now).map(|_| ())
+            );
+        }
+
+        // A window too long to add to the end time never closes
+        let session = Session {
+            name: "round-3".to_string(),
+            ended: chrono::MAX_DATETIME - Duration::minutes(1),
+            window_mins: i64::MAX,
+        };
+        assert_eq!(chrono::MAX_DATETIME, session.deadline());
+    }
+}
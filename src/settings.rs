@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use lazy_static::lazy_static;
+use log::info;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::GuildId;
+use stewart_strat::series::SeriesProfiles;
+
+use crate::config;
+use crate::protest::ProtestSettings;
+use crate::stewards::StewardRoster;
+use crate::store::Store;
+
+/// Options a guild may set as defaults for every strategy calculation
+pub const STRATEGY_DEFAULT_KEYS: &[&str] =
+    &["series", "unit", "refuel_unit", "density", "refuel_step"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuildSettings {
+    /// `key=value` options applied before those given with each strategy command
+    #[serde(default)]
+    pub strategy_defaults: Vec<(String, String)>,
+    #[serde(default)]
+    pub protests: ProtestSettings,
+    #[serde(default)]
+    pub stewards: StewardRoster,
+}
+
+lazy_static! {
+    pub static ref GUILDS: Store<HashMap<u64, GuildSettings>> = Store::open("guilds.json");
+    pub static ref SERIES: SeriesProfiles = load_series();
+}
+
+fn load_series() -> SeriesProfiles {
+    let path = &config::CONFIG.series_file;
+    match fs::read_to_string(path) {
+        Ok(json) => match SeriesProfiles::from_json(&json) {
+            Ok(series) => series,
+            Err(e) => panic!("Failed to load {}: {:#?}", path.display(), e),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            info!("No series profiles at {}", path.display());
+            SeriesProfiles::default()
+        }
+        Err(e) => panic!("Failed to load {}: {:#?}", path.display(), e),
+    }
+}
+
+/// The settings for a guild, or the defaults for direct messages and guilds which set nothing
+pub fn guild(id: Option<GuildId>) -> GuildSettings {
+    id.and_then(|id| GUILDS.read(|guilds| guilds.get(&id.0).cloned()))
+        .unwrap_or_default()
+}
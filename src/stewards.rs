@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// How stewards are picked for a protest
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Assignment {
+    /// Take turns down the roster
+    RoundRobin,
+    /// Pick those with the fewest open protests, taking turns among equals
+    #[default]
+    LeastLoaded,
+}
+
+impl Assignment {
+    pub fn parse(s: &str) -> Option<Assignment> {
+        match s.to_lowercase().as_str() {
+            "round-robin" | "round_robin" => Some(Assignment::RoundRobin),
+            "least-loaded" | "least_loaded" => Some(Assignment::LeastLoaded),
+            _ => None,
+        }
+    }
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Assignment::RoundRobin => "round-robin",
+            Assignment::LeastLoaded => "least-loaded",
+        }
+    }
+}
+
+/// The stewards protests may be assigned to, and the teams of anyone in a protest
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StewardRoster {
+    #[serde(default)]
+    pub stewards: Vec<u64>,
+    /// Team of each driver or steward who has one
+    #[serde(default)]
+    pub teams: HashMap<u64, String>,
+}
+
+impl StewardRoster {
+    /// Whether `steward` shouldn't hear a protest between `protester` and `accused`, because
+    /// they're one of them or on the same team as one of them
+    pub fn conflicted(&self, steward: u64, protester: u64, accused: &[u64]) -> bool {
+        let involved = || std::iter::once(&protester).chain(accused);
+        if involved().any(|&id| id == steward) {
+            return true;
+        }
+        match self.teams.get(&steward) {
+            Some(team) => involved().any(|id| self.teams.get(id) == Some(team)),
+            None => false,
+        }
+    }
+
+    /// Pick up to `count` stewards without a conflict of interest, starting from `cursor` in the
+    /// roster. `load` is the number of open protests each steward has. Returns the stewards and
+    /// where to start next time.
+    pub fn assign(
+        &self,
+        count: usize,
+        method: Assignment,
+        protester: u64,
+        accused: &[u64],
+        load: &HashMap<u64, usize>,
+        cursor: usize,
+    ) -> (Vec<u64>, usize) {
+        let roster = self.stewards.len();
+        if roster == 0 {
+            return (vec![], 0);
+        }
+        // Everyone eligible in the order it's their turn
+        let mut turns: Vec<usize> = (0..roster)
+            .map(|i| (cursor + i) % roster)
+            .filter(|&i| !self.conflicted(self.stewards[i], protester, accused))
+            .collect();
+        if method == Assignment::LeastLoaded {
+            turns.sort_by_key(|&i| load.get(&self.stewards[i]).copied().unwrap_or(0));
+        }
+        turns.truncate(count);
+
+        let next = match method {
+            Assignment::RoundRobin => turns.last().map(|&i| (i + 1) % roster),
+            Assignment::LeastLoaded => turns.first().map(|_| (cursor + 1) % roster),
+        };
+        (
+            turns.into_iter().map(|i| self.stewards[i]).collect(),
+            next.unwrap_or(cursor),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn roster() -> StewardRoster {
+        let mut roster = StewardRoster {
+            stewards: vec![1, 2, 3, 4],
+            teams: HashMap::new(),
+        };
+        roster.teams.insert(2, "Apex".to_string());
+        roster.teams.insert(200, "Apex".to_string());
+        roster
+    }
+
+    #[test]
+    fn skips_conflicted_stewards() {
+        let roster = roster();
+        assert!(roster.conflicted(1, 1, &[200]));
+        assert!(roster.conflicted(2, 100, &[200]));
+        assert!(!roster.conflicted(3, 100, &[200]));
+
+        let load = HashMap::new();
+        let assign = |cursor| roster.assign(2, Assignment::RoundRobin, 100, &[200], &load, cursor);
+        assert_eq!((vec![1, 3], 3), assign(0));
+        assert_eq!((vec![4, 1], 1), assign(3));
+        assert_eq!((vec![3, 4], 0), assign(1));
+    }
+
+    #[test]
+    fn prefers_the_least_loaded() {
+        let roster = roster();
+        let mut load = HashMap::new();
+        load.insert(1, 2);
+        load.insert(3, 1);
+        let (stewards, next) = roster.assign(2, Assignment::LeastLoaded, 100, &[300], &load, 0);
+        assert_eq!(vec![2, 4], stewards);
+        assert_eq!(1, next);
+
+        let (stewards, _) = roster.assign(5, Assignment::LeastLoaded, 1, &[200], &load, 0);
+        assert_eq!(vec![4, 3], stewards);
+        assert_eq!(
+            (vec![], 0),
+            StewardRoster::default().assign(2, Assignment::RoundRobin, 1, &[], &load, 0)
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::config;
+
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("Couldn't access the data file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Couldn't encode or decode the data file: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A value kept in a JSON file in the data directory, so it survives restarts
+pub struct Store<T> {
+    path: PathBuf,
+    value: RwLock<T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> Store<T> {
+    /// Load the store from `name` in the data directory, starting empty if it doesn't exist yet
+    pub fn open(name: &str) -> Store<T> {
+        let path = config::CONFIG.data_dir.join(name);
+        let value = match Store::load(&path) {
+            Ok(value) => value,
+            Err(StoreError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                info!("No data at {}, starting afresh", path.display());
+                T::default()
+            }
+            Err(e) => panic!("Failed to load {}: {:#?}", path.display(), e),
+        };
+
+        Store {
+            path,
+            value: RwLock::new(value),
+        }
+    }
+
+    fn load(path: &PathBuf) -> Result<T, StoreError> {
+        let contents = fs::read(path)?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.value.read().expect("store lock poisoned"))
+    }
+
+    /// Change the value and write it straight back to disk
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, StoreError> {
+        let mut value = self.value.write().expect("store lock poisoned");
+        let result = f(&mut value);
+
+        // Write to a temporary file first so a crash can't leave us with half a file
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = self.path.with_extension("tmp");
+        fs::write(&temp, serde_json::to_vec_pretty(&*value)?)?;
+        fs::rename(&temp, &self.path)?;
+
+        Ok(result)
+    }
+}
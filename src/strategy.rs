@@ -1,495 +1,247 @@
-use std::time::Duration;
-
+use chrono::{DateTime, Utc};
 use serenity::framework::standard::Args;
+use serenity::model::id::{GuildId, UserId};
+use serenity::utils::parse_username;
+use std::time::Duration;
+use stewart_strat::simulation::SimulationOutcome;
+use stewart_strat::team::{self, TeamStrategy};
+use stewart_strat::{
+    format_energy, round_secs, Arguments, Strategy, StrategyError, StrategyInner, StrategyInput,
+};
+
+use crate::settings;
+
+/// How the bot lays out a strategy in its reply
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// A field per strategy, spelling out each stint and stop
+    Fields,
+    /// A compact table per strategy in a code block
+    Table,
+}
 
-use crate::discord::CommandErr;
-
+/// Options which change how the bot presents results, rather than the calculation itself
 #[derive(Debug, Clone)]
-pub enum Strategy {
-    SingleStint(StrategyInner),
-    LongStints(StrategyInner),
-    EqualStints(StrategyInner),
+pub struct DisplayOptions {
+    pub layout: Layout,
 }
 
-// Accept either minutes or HH:MM
-fn parse_mins_or_hhmm(input: &str) -> Result<Duration, CommandErr> {
-    if !input.contains(':') {
-        let i = input
-            .parse::<u32>()
-            .map_err(|_| CommandErr::InvalidCommandArgument)?;
-        return Ok(Duration::new(i as u64 * 60, 0));
-    } else {
-        let parts: Vec<&str> = input.split(':').collect();
-        if parts.len() == 2 {
-            let hours = parts[0].parse::<u32>().ok();
-            let minutes = parts[1].parse::<u32>().ok();
-
-            if let (Some(hours), Some(minutes)) = (hours, minutes) {
-                let mins: u64 = (hours * 60 + minutes) as u64;
-                return Ok(Duration::new(mins * 60, 0));
-            }
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        DisplayOptions {
+            layout: Layout::Fields,
         }
     }
-    Err(CommandErr::InvalidCommandArgument)
 }
 
-fn parse_mmss(input: &str) -> Result<Duration, CommandErr> {
-    let parts: Vec<&str> = input.split(':').collect();
-    if parts.len() == 2 {
-        let mins = parts[0].parse::<u32>().ok();
-        let secs = parts[1].parse::<u32>().ok();
+/// Hand the words of a command over to the strategy calculator's own parser, along with any
+/// defaults the guild has set
+pub fn arguments(args: &Args, guild: Option<GuildId>) -> Arguments {
+    with_settings(Arguments::new(args.raw_quoted()), guild)
+}
+
+/// Apply the guild's defaults and the series profiles to arguments
+fn with_settings(args: Arguments, guild: Option<GuildId>) -> Arguments {
+    args.with_defaults(settings::guild(guild).strategy_defaults)
+        .with_series(settings::SERIES.clone())
+}
 
-        if let (Some(mins), Some(secs)) = (mins, secs) {
-            return Ok(Duration::new((secs + mins * 60) as u64, 0));
-        }
-    }
-    Err(CommandErr::InvalidCommandArgument)
+/// As [`arguments`], but first taking out any options meant for the bot rather than the calculator
+pub fn arguments_with_display(
+    args: &Args,
+    guild: Option<GuildId>,
+) -> Result<(Arguments, DisplayOptions), StrategyError> {
+    let (remaining, display) = split_display(args.raw_quoted())?;
+    Ok((with_settings(Arguments::new(remaining), guild), display))
 }
 
-impl Strategy {
-    pub fn as_discord_text(&self) -> String {
-        match self {
-            Strategy::SingleStint(inner) => inner.as_discord_text(),
-            Strategy::LongStints(inner) => inner.as_discord_text(),
-            Strategy::EqualStints(inner) => inner.as_discord_text(),
+/// Separate the options meant for the bot from those meant for the calculator
+pub fn split_display<'a, I: IntoIterator<Item = &'a str>>(
+    args: I,
+) -> Result<(Vec<&'a str>, DisplayOptions), StrategyError> {
+    let mut display = DisplayOptions::default();
+    let mut remaining = vec![];
+
+    for arg in args {
+        match arg.split_once('=') {
+            Some(("layout", "fields")) => display.layout = Layout::Fields,
+            Some(("layout", "table")) => display.layout = Layout::Table,
+            Some(("layout", value)) => {
+                return Err(StrategyError::InvalidArgument(
+                    "layout".to_string(),
+                    value.to_string(),
+                ))
+            }
+            _ => remaining.push(arg),
         }
     }
 
-    pub fn discord_title(&self) -> &str {
-        match self {
-            Strategy::SingleStint(_) => "Single Stint",
-            Strategy::LongStints(_) => "Longer Stints",
-            Strategy::EqualStints(_) => "Equal Stints",
-        }
-    }
+    Ok((remaining, display))
+}
 
-    pub fn from_discord_args(
-        args: &mut Args,
-    ) -> Result<Vec<Strategy>, Box<dyn std::error::Error + Send + Sync>> {
-        // Ordering must be preserved
-        let race_time = parse_mins_or_hhmm(&args.single::<String>()?)?;
-        let lap_time = parse_mmss(&args.single::<String>()?)?;
-        let fuel_per_lap = args.single::<f64>()?;
-        let fuel_capacity = args.single::<u32>()?;
+/// As [`arguments`], for two cars whose arguments are separated by [`team::SEPARATOR`]
+pub fn team_arguments(
+    args: &Args,
+    guild: Option<GuildId>,
+) -> Result<(Arguments, Arguments), StrategyError> {
+    let (first, second) = team::split(args.raw_quoted().map(String::from).collect())?;
+    Ok((
+        with_settings(Arguments::new(first), guild),
+        with_settings(Arguments::new(second), guild),
+    ))
+}
 
-        // Optional args, mandatory pitstops and max stint time
-        let mandatory_pits = if !args.is_empty() {
-            Some(args.single::<u8>()?)
-        } else {
-            None
-        };
+/// As [`arguments`], but first taking out the drivers mentioned, in the order they drive
+pub fn race_arguments(args: &Args, guild: Option<GuildId>) -> (Arguments, Vec<UserId>) {
+    let (drivers, remaining): (Vec<&str>, Vec<&str>) = args
+        .raw_quoted()
+        .partition(|arg| parse_username(arg).is_some());
+    let drivers = drivers
+        .into_iter()
+        .filter_map(parse_username)
+        .map(UserId)
+        .collect();
+    (with_settings(Arguments::new(remaining), guild), drivers)
+}
 
-        let permitted_max_stint_length = if !args.is_empty() {
-            Some(parse_mins_or_hhmm(&args.single::<String>()?)?)
-        } else {
-            None
-        };
-        // End preserve ordering
+/// Read options for a guild's strategy defaults, checking each is one a guild may set and that
+/// the calculator accepts its value
+pub fn parse_defaults(args: &Args) -> Result<Vec<(String, String)>, StrategyError> {
+    let options = Arguments::new(args.raw_quoted()).options()?;
+    if let Some((key, _)) = options
+        .iter()
+        .find(|(key, _)| !settings::STRATEGY_DEFAULT_KEYS.contains(&key.as_str()))
+    {
+        return Err(StrategyError::UnknownOption(key.clone()));
+    }
+
+    // Check the values by applying them, with any series expanded into its rules
+    let expanded = Arguments::new(args.raw_quoted())
+        .with_series(settings::SERIES.clone())
+        .options()?;
+    let mut sample = StrategyInput::builder(
+        Duration::from_secs(3600),
+        Duration::from_secs(120),
+        3.0,
+        100.0,
+    )
+    .build()?;
+
+    for (key, value) in &expanded {
+        if !sample.apply_option(key, value)? {
+            return Err(StrategyError::UnknownOption(key.clone()));
+        }
+    }
+    sample.validate()?;
+    Ok(options)
+}
 
-        let strategy_input = StrategyInput {
-            race_duration: race_time,
-            avg_laptime: lap_time,
-            fuel_per_lap,
-            fuel_capacity,
-            mandatory_pits,
-            permitted_max_stint_length,
-        };
+/// Formatting of calculator results for an embed field
+pub trait DiscordText {
+    fn as_discord_text(&self) -> String;
+}
 
-        Ok(strategy_input.calculate())
+impl DiscordText for Strategy {
+    fn as_discord_text(&self) -> String {
+        self.inner().as_discord_text()
     }
 }
 
-impl StrategyInner {
+impl DiscordText for StrategyInner {
     fn as_discord_text(&self) -> String {
         let mut output = String::new();
         output.push_str("**Starting Fuel**\n");
         output.push_str(&format!(
-            "{} L\n{} Laps",
-            self.stints[0].fuel_required, self.stints[0].laps
+            "{}\n{} Laps",
+            self.fuel_units.format(self.starting_fuel()),
+            self.stints[0].laps
         ));
+        if let Some(energy) = self.stints[0].energy_required {
+            output.push_str(&format!("\n{} Energy", format_energy(energy)));
+        }
+        if let Some(tyres) = self.stints[0].tyres {
+            output.push_str(&format!("\n{}", tyres.name()));
+        }
         for (i, stint) in self.stints.iter().enumerate() {
             output.push_str(&format!(
                 "\n\n**Stint {}**\n{}",
                 i + 1,
-                humantime::format_duration(stint.duration)
+                humantime::format_duration(round_secs(stint.duration))
             ));
+            if let Some(start_time) = stint.start_time {
+                output.push_str(&format!("\nStarts {}", discord_timestamp(start_time)));
+            }
             if i < self.stops.len() {
                 output.push_str(&format!(
-                    "\n\n**Stop {}**\nLap {}\nAdd fuel: {} L",
+                    "\n\n**Stop {}**\nLap {}\nAdd fuel: {}",
                     i + 1,
                     self.stops[i].lap,
-                    self.stops[i].fuel_to_add
+                    self.fuel_units.format(self.stops[i].fuel_to_add)
                 ));
+                if let Some(tyres) = self.stops[i].tyres {
+                    output.push_str(&format!("\nFit: {}", tyres.name()));
+                }
+                if let Some(energy) = self.stops[i].energy_to_add {
+                    output.push_str(&format!("\nAdd energy: {}", format_energy(energy)));
+                }
+                if let Some(time) = self.stops[i].time {
+                    output.push_str(&format!("\nIn at {}", discord_timestamp(time)));
+                }
             }
         }
+        let fuel_weight_cost = self.fuel_weight_cost();
+        if fuel_weight_cost.as_secs() > 0 {
+            output.push_str(&format!(
+                "\n\n**Fuel Weight Cost**\n{}",
+                humantime::format_duration(round_secs(fuel_weight_cost))
+            ));
+        }
         output
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct StrategyInner {
-    pub stints: Vec<Stint>,
-    pub stops: Vec<Stop>,
-}
-
-#[derive(Debug, Clone)]
-pub struct StrategyInput {
-    pub race_duration: Duration,
-    pub avg_laptime: Duration,
-    pub fuel_per_lap: f64,
-    pub fuel_capacity: u32,
-    pub mandatory_pits: Option<u8>,
-    pub permitted_max_stint_length: Option<Duration>,
-}
-
-#[derive(Debug, Clone)]
-pub struct Stint {
-    pub duration: Duration,
-    pub laps: u32,
-    pub fuel_required: u32,
-}
-
-#[derive(Debug, Clone)]
-pub struct Stop {
-    pub lap: u32,
-    pub fuel_to_add: u32,
-}
-
-impl StrategyInput {
-    fn fuel_duration(&self, fuel: u32) -> Duration {
-        // Intentionally truncate the laps value here by discarding the fractional part
-        let laps = (fuel as f64 / self.fuel_per_lap) as u32;
-        laps * self.avg_laptime
-    }
-
-    fn max_fuel_duration(&self) -> Duration {
-        self.fuel_duration(self.fuel_capacity)
-    }
-
-    fn fuel_for_stint(&self, length: Duration) -> u32 {
-        let laps = (length.as_secs_f64() / self.avg_laptime.as_secs_f64()).ceil();
-        (laps * self.fuel_per_lap).ceil() as u32
-    }
-
-    /// The longest possible stint duration based on regulations and fuel capacity
-    fn max_stint_time(&self) -> Duration {
-        if let Some(stint_time) = self.permitted_max_stint_length {
-            std::cmp::min(stint_time, self.max_fuel_duration())
-        } else {
-            self.max_fuel_duration()
-        }
-    }
-
-    /// How many stints are required based only on fuel consumption and capacity
-    fn fuel_required_stints(&self) -> u8 {
-        let stints = self.race_duration.as_secs_f64() / self.max_fuel_duration().as_secs_f64();
-        stints.ceil() as u8
-    }
-
-    /// How many stints are required given the number of mandatory pits in the input
-    fn mandatory_pits_required_stints(&self) -> u8 {
-        if let Some(required) = self.mandatory_pits {
-            required + 1
-        } else {
-            1
-        }
-    }
-
-    /// How many stints are required given the maximum permitted stint length in the input
-    fn permitted_stint_length_required_stints(&self) -> u8 {
-        if let Some(max) = self.permitted_max_stint_length {
-            (self.race_duration.as_secs_f64() / max.as_secs_f64()).ceil() as u8
-        } else {
-            1
-        }
-    }
-
-    /// How many stints are required, taking into account fuel and regulations
-    fn required_stints(&self) -> u8 {
-        std::cmp::max(
-            std::cmp::max(
-                self.fuel_required_stints(),
-                self.mandatory_pits_required_stints(),
-            ),
-            self.permitted_stint_length_required_stints(),
+impl DiscordText for SimulationOutcome {
+    fn as_discord_text(&self) -> String {
+        format!(
+            "**Expected Finish**\n{}\n\n**Pitting Under Caution**\n{}\n\n**Opportunistic Stop Wins**\n{:.0}% of races",
+            humantime::format_duration(round_secs(self.expected_finish)),
+            humantime::format_duration(round_secs(self.expected_opportunistic_finish)),
+            self.opportunistic_win_rate * 100.0
         )
     }
+}
 
-    // If we require more stints than the car's endurance allows, these must be mandatory
-    // stops which are assumed to involve a tyre change. In these circumstances, running long
-    // stints is of no advantage so we should show only the even-stints model
-    fn all_pits_mandatory(&self) -> bool {
-        let time_required_stints =
-            (self.race_duration.as_secs_f64() / self.max_stint_time().as_secs_f64()).ceil() as u32;
-
-        if let Some(mandatory) = self.mandatory_pits {
-            (time_required_stints - 1) <= mandatory as u32
-        } else {
-            false
-        }
-    }
-
-    fn calculate_stints(&self, target_stint_time: Duration) -> Vec<Stint> {
-        let mut remaining_race_time = self.race_duration;
-
-        let mut stints = vec![];
-        while remaining_race_time.as_secs() != 0 {
-            let this_stint_time = std::cmp::min(remaining_race_time, target_stint_time);
-            stints.push(Stint {
-                duration: this_stint_time,
-                fuel_required: self.fuel_for_stint(this_stint_time),
-                laps: (this_stint_time.as_secs_f64() / self.avg_laptime.as_secs_f64()).ceil()
-                    as u32,
-            });
-
-            // This can panic if there's an overflowing subtraction, so just zero it if that would occur
-            remaining_race_time = if this_stint_time >= remaining_race_time {
-                Duration::new(0, 0)
-            } else {
-                remaining_race_time - this_stint_time
-            };
-        }
-
-        stints
-    }
-
-    fn calculate_even_stint_strategy(&self) -> Strategy {
-        let remaining_required_stints = self.required_stints();
-        let target_stint_time = Duration::from_secs_f64(
-            (self.race_duration.as_secs_f64() / remaining_required_stints as f64).ceil(),
-        );
-
-        let stints = self.calculate_stints(target_stint_time);
-        let mut stops: Vec<Stop> = vec![];
-        for i in 1..stints.len() {
-            let stint_laps = (stints[i - 1].duration.as_secs_f64() / self.avg_laptime.as_secs_f64())
-                .ceil() as u32;
-            let previous_laps = if let Some(last_stop) = stops.last() {
-                last_stop.lap
-            } else {
-                0
-            };
-            stops.push(Stop {
-                lap: previous_laps + stint_laps,
-                fuel_to_add: stints[i].fuel_required,
-            });
+impl DiscordText for TeamStrategy {
+    fn as_discord_text(&self) -> String {
+        let mut output = String::new();
+        if self.moved_stops.is_empty() && self.conflicts.is_empty() {
+            output.push_str("The cars never need the pit box at the same time.");
         }
-
-        Strategy::EqualStints(StrategyInner { stints, stops })
-    }
-
-    fn calculate_long_stint_strategy(&self) -> Strategy {
-        let target_stint_time = self.max_stint_time();
-        let stints = self.calculate_stints(target_stint_time);
-
-        let mut stops: Vec<Stop> = vec![];
-        for i in 1..stints.len() {
-            let stint_laps = (stints[i - 1].duration.as_secs_f64() / self.avg_laptime.as_secs_f64())
-                .ceil() as u32;
-            let previous_laps = if let Some(last_stop) = stops.last() {
-                last_stop.lap
-            } else {
-                0
-            };
-            stops.push(Stop {
-                lap: previous_laps + stint_laps,
-                fuel_to_add: stints[i].fuel_required,
-            });
+        for moved in &self.moved_stops {
+            output.push_str(&format!(
+                "Car 2 stops on lap {} instead of {} to stay clear of car 1.\n",
+                moved.lap, moved.planned_lap
+            ));
         }
-
-        Strategy::LongStints(StrategyInner { stints, stops })
-    }
-
-    fn calculate_single_stint(&self) -> Strategy {
-        let target_stint_time = self.max_stint_time();
-        let stints = self.calculate_stints(target_stint_time);
-
-        Strategy::SingleStint(StrategyInner {
-            stints,
-            stops: vec![],
-        })
-    }
-
-    pub fn calculate(&self) -> Vec<Strategy> {
-        // If a single stint is possible, return that alone
-        if self.required_stints() == 1 {
-            return vec![self.calculate_single_stint()];
+        for conflict in &self.conflicts {
+            output.push_str(&format!(
+                "Both cars need the box on laps {} and {}, costing {}.\n",
+                conflict.laps[0],
+                conflict.laps[1],
+                humantime::format_duration(round_secs(conflict.cost))
+            ));
         }
-
-        let mut result = vec![];
-        if !self.all_pits_mandatory() {
-            // Running long stints is worthwhile as the last pitstops don't require a tyre change
-            result.push(self.calculate_long_stint_strategy());
+        if !self.conflicts.is_empty() {
+            output.push_str(&format!(
+                "**Total lost to sharing the box: {}**",
+                humantime::format_duration(round_secs(self.conflict_cost()))
+            ));
         }
-        result.push(self.calculate_even_stint_strategy());
-        result
+        output.trim_end().to_string()
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn test_fuel_req_only() {
-        let mut input = StrategyInput {
-            race_duration: Duration::new(7200, 0), // 2 hrs
-            avg_laptime: Duration::new(138, 0),    // 2:18
-            fuel_per_lap: 3.93,
-            fuel_capacity: 110,
-            permitted_max_stint_length: None,
-            mandatory_pits: None,
-        };
-
-        assert_eq!(2, input.required_stints());
-
-        input.race_duration = Duration::new(600, 0); // 10m
-        assert_eq!(1, input.required_stints());
-    }
-
-    #[test]
-    fn test_stints_mandatory_pit() {
-        let mut input = StrategyInput {
-            race_duration: Duration::new(7200, 0), // 2 hrs
-            avg_laptime: Duration::new(138, 0),    // 2:18
-            fuel_per_lap: 3.93,
-            fuel_capacity: 110,
-            permitted_max_stint_length: None,
-            mandatory_pits: Some(2),
-        };
-
-        assert_eq!(3, input.required_stints());
-
-        input.race_duration = Duration::new(600, 0); // 10m
-        assert_eq!(3, input.required_stints());
-    }
-
-    #[test]
-    fn test_stints_max_time() {
-        let input = StrategyInput {
-            race_duration: Duration::new(7200, 0), // 2 hrs
-            avg_laptime: Duration::new(138, 0),    // 2:18
-            fuel_per_lap: 3.93,
-            fuel_capacity: 125,
-            permitted_max_stint_length: Some(Duration::new(3540, 0)), // 55mins
-            mandatory_pits: None,
-        };
-
-        assert_eq!(3, input.required_stints());
-    }
-
-    #[test]
-    fn test_all_mandatory_pits() {
-        let mut input = StrategyInput {
-            race_duration: Duration::new(7200, 0), // 2 hrs
-            avg_laptime: Duration::new(138, 0),    // 2:18
-            fuel_per_lap: 3.93,
-            fuel_capacity: 125,
-            permitted_max_stint_length: Some(Duration::new(3540, 0)), // 55mins
-            mandatory_pits: Some(1),
-        };
-        assert_eq!(false, input.all_pits_mandatory());
-
-        input.mandatory_pits = Some(2);
-        assert_eq!(true, input.all_pits_mandatory());
-    }
-
-    #[test]
-    fn calculate_even_stints_strategy() {
-        let input = StrategyInput {
-            race_duration: Duration::new(8640, 0), // 2 hrs
-            avg_laptime: Duration::new(138, 0),    // 2:18
-            fuel_per_lap: 3.93,
-            fuel_capacity: 125,
-            permitted_max_stint_length: Some(Duration::new(3540, 0)), // 55mins
-            mandatory_pits: None,
-        };
-
-        let result = input.calculate_even_stint_strategy();
-        dbg!(&result);
-        match result {
-            Strategy::EqualStints(strat) => {
-                assert_eq!(3, strat.stints.len());
-                assert_eq!(2, strat.stops.len());
-
-                assert_eq!(21, strat.stops[0].lap);
-            }
-            _ => panic!("Got wrong enum variant"),
-        }
-    }
-
-    #[test]
-    fn calculate_long_stint_strategy() {
-        let input = StrategyInput {
-            race_duration: Duration::new(14400, 0), // 4 hrs
-            avg_laptime: Duration::new(138, 0),     // 2:18
-            fuel_per_lap: 3.25,
-            fuel_capacity: 110,
-            permitted_max_stint_length: None,
-            mandatory_pits: Some(3),
-        };
-
-        let result = input.calculate_long_stint_strategy();
-        match result {
-            Strategy::LongStints(strat) => {
-                assert_eq!(4, strat.stints.len());
-                assert_eq!(3, strat.stops.len());
-
-                assert_eq!(33, strat.stops[0].lap);
-                assert_eq!(108, strat.stops[0].fuel_to_add);
-            }
-            _ => panic!("Got wrong enum variant"),
-        }
-    }
-
-    #[test]
-    fn calculate_simple_strategy() {
-        let input = StrategyInput {
-            race_duration: Duration::new(3600, 0), // 1 hrs
-            avg_laptime: Duration::new(138, 0),    // 2:18
-            fuel_per_lap: 3.25,
-            fuel_capacity: 110,
-            permitted_max_stint_length: None,
-            mandatory_pits: None,
-        };
-
-        let result = input.calculate();
-        assert_eq!(1, result.len());
-        match &result[0] {
-            Strategy::SingleStint(strat) => {
-                assert_eq!(strat.stints[0].fuel_required, 88);
-            }
-            _ => panic!("Got wrong type of strategy for single stint race"),
-        }
-    }
-
-    #[test]
-    fn calculates_both_strategies() {
-        let input = StrategyInput {
-            race_duration: Duration::new(14400, 0), // 4 hrs
-            avg_laptime: Duration::new(138, 0),     // 2:18
-            fuel_per_lap: 3.90,
-            fuel_capacity: 110,
-            permitted_max_stint_length: None,
-            mandatory_pits: None,
-        };
-
-        let result = input.calculate();
-        assert_eq!(2, result.len());
-    }
-
-    #[test]
-    fn calculates_only_one_strategy_where_appropriate() {
-        let input = StrategyInput {
-            race_duration: Duration::new(14400, 0), // 4 hrs
-            avg_laptime: Duration::new(138, 0),     // 2:18
-            fuel_per_lap: 3.90,
-            fuel_capacity: 110,
-            permitted_max_stint_length: None,
-            mandatory_pits: Some(3), // All pitstops are tyre changes, so the long-stints strategy doesn't make sense
-        };
-
-        let result = input.calculate();
-        assert_eq!(1, result.len());
-    }
+/// Format a time for Discord to render in each reader's own timezone
+fn discord_timestamp(time: DateTime<Utc>) -> String {
+    format!("<t:{}:t>", time.timestamp())
 }
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use log::{error, info};
+use serenity::http::Http;
+use serenity::model::id::ChannelId;
+use warp::http::StatusCode;
+use warp::{Rejection, Reply};
+
+use crate::discord;
+use crate::race::{self, Telemetry};
+
+/// Take a lap report from a telemetry relay for the race in `channel`, posting the new plan to
+/// the channel if it moves the next stop
+pub async fn telemetry_handler(
+    channel: u64,
+    authorization: Option<String>,
+    report: Telemetry,
+    http: Arc<Http>,
+) -> Result<impl Reply, Rejection> {
+    let token = authorization
+        .as_deref()
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    let authorised = race::RACES.read(|races| {
+        races
+            .get(&channel)
+            .map(|race| race.telemetry_authorised(token))
+    });
+    match authorised {
+        None => return Ok(StatusCode::NOT_FOUND),
+        Some(false) => return Ok(StatusCode::UNAUTHORIZED),
+        Some(true) => {}
+    }
+
+    let now = Utc::now();
+    let update = race::RACES.update(|races| {
+        races
+            .get_mut(&channel)
+            .and_then(|race| race.record(report, now))
+    });
+    match update {
+        Ok(Some((was, plan))) => {
+            info!("Telemetry moved the next stop in channel {}", channel);
+            if let Err(e) = discord::send_plan_update(&http, ChannelId(channel), was, &plan).await {
+                error!("Failed to post plan update to {}: {:?}", channel, e);
+            }
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(None) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => {
+            error!("Failed to save telemetry for {}: {}", channel, e);
+            Ok(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+
+use crate::config;
+use crate::protest::Protest;
+
+/// A message from a protest channel, as it's written in the transcript
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub author: String,
+    pub at: DateTime<Utc>,
+    pub content: String,
+    /// Links to files attached to the message
+    pub attachments: Vec<String>,
+    /// Embeds, each already written out as text
+    pub embeds: Vec<String>,
+}
+
+/// The Markdown transcript of a protest and the messages in its channel, oldest first
+pub fn markdown(protest: &Protest, entries: &[Entry]) -> String {
+    let mut text = format!(
+        "# Protest: {}\n\nLodged by <@{}> on {}",
+        protest.name,
+        protest.protester,
+        protest.created.format("%Y-%m-%d %H:%M UTC")
+    );
+    if !protest.accused.is_empty() {
+        let accused: Vec<String> = protest
+            .accused
+            .iter()
+            .map(|id| format!("<@{}>", id))
+            .collect();
+        text.push_str(&format!(" against {}", accused.join(", ")));
+    }
+    text.push_str(".\n\n## Status\n\n");
+    text.push_str(&format!(
+        "* {}: Submitted\n",
+        protest.created.format("%Y-%m-%d %H:%M UTC")
+    ));
+    for change in &protest.changes {
+        text.push_str(&format!(
+            "* {}: {} by <@{}>\n",
+            change.at.format("%Y-%m-%d %H:%M UTC"),
+            change.status.name(),
+            change.by
+        ));
+    }
+
+    text.push_str("\n## Messages\n");
+    for entry in entries {
+        text.push_str(&format!(
+            "\n**{}** at {}\n\n",
+            entry.author,
+            entry.at.format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+        if !entry.content.is_empty() {
+            for line in entry.content.lines() {
+                text.push_str(&format!("> {}\n", line));
+            }
+        }
+        for attachment in &entry.attachments {
+            text.push_str(&format!("* Attachment: <{}>\n", attachment));
+        }
+        for embed in &entry.embeds {
+            text.push_str("* Embed:\n");
+            for line in embed.lines() {
+                text.push_str(&format!("  > {}\n", line));
+            }
+        }
+    }
+    text
+}
+
+/// The file name for a protest's transcript
+pub fn file_name(protest: &Protest) -> String {
+    format!("{}-{}.md", protest.channel, protest.name)
+}
+
+/// Keep a transcript in the data directory, returning where it was written
+pub fn save(protest: &Protest, transcript: &str) -> io::Result<PathBuf> {
+    let dir = config::CONFIG
+        .data_dir
+        .join("transcripts")
+        .join(protest.guild.to_string());
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(file_name(protest));
+    fs::write(&path, transcript)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn writes_every_message() {
+        let at = "2021-03-14T20:05:00Z".parse::<DateTime<Utc>>().unwrap();
+        let mut protest = Protest::new(1, 2, "t1-crash".to_string(), 100, vec![200], at);
+        protest
+            .set_status(crate::protest::ProtestStatus::Closed, 300, at)
+            .unwrap();
+        let transcript = markdown(
+            &protest,
+            &[Entry {
+                author: "Driver#0001".to_string(),
+                at,
+                content: "He hit me\nat turn 1".to_string(),
+                attachments: vec!["https://cdn.example/onboard.mp4".to_string()],
+                embeds: vec!["Protest: t1-crash".to_string()],
+            }],
+        );
+        assert!(transcript.starts_with("# Protest: t1-crash"));
+        assert!(transcript.contains("against <@200>"));
+        assert!(transcript.contains("2021-03-14 20:05 UTC: Closed by <@300>"));
+        assert!(transcript.contains("**Driver#0001** at 2021-03-14 20:05:00 UTC"));
+        assert!(transcript.contains("> He hit me\n> at turn 1\n"));
+        assert!(transcript.contains("* Attachment: <https://cdn.example/onboard.mp4>"));
+        assert!(transcript.contains("  > Protest: t1-crash"));
+    }
+}
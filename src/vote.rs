@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Votes needed for a result unless the vote asks for another number
+pub const DEFAULT_QUORUM: usize = 3;
+
+/// How long a vote stays open unless it's given a deadline
+pub const DEFAULT_DEADLINE_HOURS: i64 = 24;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum VoteError {
+    #[error("a vote needs at least two options")]
+    TooFewOptions,
+    #[error("`{0}` is an option more than once")]
+    DuplicateOption(String),
+    #[error("`{0}={1}` isn't valid, {2}")]
+    InvalidSetting(String, String, &'static str),
+    #[error("`{0}` isn't one of the options, vote for {1}")]
+    UnknownChoice(String, String),
+    #[error("there's already a vote open on this protest")]
+    AlreadyOpen,
+    #[error("there's no vote open on this protest")]
+    NotOpen,
+    #[error("voting closed at {}", .0.format("%Y-%m-%d %H:%M UTC"))]
+    PastDeadline(DateTime<Utc>),
+}
+
+/// How a vote came out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum VoteResult {
+    Decided(String),
+    /// The options which shared the most votes
+    Tied(Vec<String>),
+    /// Too few stewards voted for a result
+    NoQuorum,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Outcome {
+    pub result: VoteResult,
+    /// Votes for each option, in the order they were given
+    pub tally: Vec<(String, usize)>,
+    pub closed: DateTime<Utc>,
+    /// Steward who closed the vote, or None if it ran to its deadline
+    pub closed_by: Option<u64>,
+}
+
+/// A vote among the stewards on the outcome of a protest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Vote {
+    pub options: Vec<String>,
+    /// Keep the tally to ourselves until the vote closes
+    pub hidden: bool,
+    pub quorum: usize,
+    pub opened_by: u64,
+    pub opened: DateTime<Utc>,
+    pub deadline: DateTime<Utc>,
+    /// Each steward's choice, as an index into the options
+    pub ballots: HashMap<u64, usize>,
+    pub outcome: Option<Outcome>,
+}
+
+impl Vote {
+    /// Open a vote from its options and any settings among them: `hidden`, `quorum=<n>` and
+    /// `deadline=<time>`, e.g. `deadline=30m` or `deadline=2d`
+    pub fn open(args: &[String], opened_by: u64, now: DateTime<Utc>) -> Result<Vote, VoteError> {
+        let mut options: Vec<String> = vec![];
+        let mut hidden = false;
+        let mut quorum = DEFAULT_QUORUM;
+        let mut deadline = now + Duration::hours(DEFAULT_DEADLINE_HOURS);
+
+        for arg in args {
+            let invalid = |reason| {
+                let (key, value) = arg.split_once('=').unwrap_or_default();
+                VoteError::InvalidSetting(key.to_string(), value.to_string(), reason)
+            };
+            if arg.eq_ignore_ascii_case("hidden") {
+                hidden = true;
+            } else if let Some(value) = arg.strip_prefix("quorum=") {
+                quorum = value
+                    .parse()
+                    .ok()
+                    .filter(|&quorum| quorum > 0)
+                    .ok_or_else(|| invalid("give the number of votes needed"))?;
+            } else if let Some(value) = arg.strip_prefix("deadline=") {
+                deadline = parse_period(value)
+                    .and_then(|period| now.checked_add_signed(period))
+                    .ok_or_else(|| {
+                        invalid("give a time of up to a year, such as 30m, 12h or 2d")
+                    })?;
+            } else if options
+                .iter()
+                .any(|option| option.eq_ignore_ascii_case(arg))
+            {
+                return Err(VoteError::DuplicateOption(arg.clone()));
+            } else {
+                options.push(arg.clone());
+            }
+        }
+
+        if options.len() < 2 {
+            return Err(VoteError::TooFewOptions);
+        }
+        Ok(Vote {
+            options,
+            hidden,
+            quorum,
+            opened_by,
+            opened: now,
+            deadline,
+            ballots: HashMap::new(),
+            outcome: None,
+        })
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.outcome.is_none()
+    }
+
+    /// Whether the vote is still open after its deadline
+    pub fn overdue(&self, now: DateTime<Utc>) -> bool {
+        self.is_open() && now >= self.deadline
+    }
+
+    /// Record a steward's vote for an option, given by name or number, replacing any vote they've
+    /// already cast. Returns the option voted for.
+    pub fn cast(
+        &mut self,
+        voter: u64,
+        choice: &str,
+        now: DateTime<Utc>,
+    ) -> Result<&str, VoteError> {
+        if !self.is_open() {
+            return Err(VoteError::NotOpen);
+        }
+        if now >= self.deadline {
+            return Err(VoteError::PastDeadline(self.deadline));
+        }
+        let index = self
+            .options
+            .iter()
+            .position(|option| option.eq_ignore_ascii_case(choice))
+            .or_else(|| {
+                choice
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&n| n >= 1 && n <= self.options.len())
+                    .map(|n| n - 1)
+            })
+            .ok_or_else(|| VoteError::UnknownChoice(choice.to_string(), self.choices()))?;
+        self.ballots.insert(voter, index);
+        Ok(&self.options[index])
+    }
+
+    pub fn tally(&self) -> Vec<(String, usize)> {
+        let mut counts = vec![0; self.options.len()];
+        for &index in self.ballots.values() {
+            counts[index] += 1;
+        }
+        self.options.iter().cloned().zip(counts).collect()
+    }
+
+    /// Count the votes and close the vote
+    pub fn close(&mut self, by: Option<u64>, now: DateTime<Utc>) -> &Outcome {
+        let tally = self.tally();
+        let most = tally.iter().map(|(_, votes)| *votes).max().unwrap_or(0);
+        let leaders: Vec<String> = tally
+            .iter()
+            .filter(|(_, votes)| *votes == most)
+            .map(|(option, _)| option.clone())
+            .collect();
+        let result = if self.ballots.len() < self.quorum {
+            VoteResult::NoQuorum
+        } else if leaders.len() == 1 {
+            VoteResult::Decided(leaders[0].clone())
+        } else {
+            VoteResult::Tied(leaders)
+        };
+        self.outcome.insert(Outcome {
+            result,
+            tally,
+            closed: now,
+            closed_by: by,
+        })
+    }
+
+    /// The options, numbered as they may be voted for
+    pub fn choices(&self) -> String {
+        self.options
+            .iter()
+            .enumerate()
+            .map(|(i, option)| format!("{}. `{}`", i + 1, option))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Where the vote stands, without giving away a hidden tally
+    pub fn describe(&self) -> String {
+        let tally = |tally: &[(String, usize)]| {
+            tally
+                .iter()
+                .map(|(option, votes)| format!("`{}`: {}", option, votes))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match &self.outcome {
+            Some(outcome) => {
+                let result = match &outcome.result {
+                    VoteResult::Decided(option) => format!("the stewards decided **{}**", option),
+                    VoteResult::Tied(options) => format!(
+                        "the stewards were tied between **{}**",
+                        options.join("** and **")
+                    ),
+                    VoteResult::NoQuorum => format!(
+                        "too few stewards voted, {} of the {} needed",
+                        self.ballots.len(),
+                        self.quorum
+                    ),
+                };
+                format!(
+                    "The vote closed at {} and {}. Votes: {}.",
+                    outcome.closed.format("%Y-%m-%d %H:%M UTC"),
+                    result,
+                    tally(&outcome.tally)
+                )
+            }
+            None => {
+                let votes = if self.hidden {
+                    format!(
+                        "{} votes cast so far, kept hidden until the vote closes",
+                        self.ballots.len()
+                    )
+                } else {
+                    format!("Votes so far: {}", tally(&self.tally()))
+                };
+                format!(
+                    "Stewards, vote with `!vote <option>` for one of {}. Voting closes at {} and needs {} votes. {}.",
+                    self.choices(),
+                    self.deadline.format("%Y-%m-%d %H:%M UTC"),
+                    self.quorum,
+                    votes
+                )
+            }
+        }
+    }
+}
+
+/// Longest period we'll take, so that nothing can overflow adding it to a time
+const MAX_PERIOD_MINS: i64 = 366 * 24 * 60;
+
+/// Read a period such as `30m`, `12h` or `2d`, of up to a year
+pub fn parse_period(s: &str) -> Option<Duration> {
+    let (amount, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit())?);
+    let amount: i64 = amount.parse().ok().filter(|&amount| amount > 0)?;
+    let minutes = match unit {
+        "m" => Some(amount),
+        "h" => amount.checked_mul(60),
+        "d" => amount.checked_mul(24 * 60),
+        _ => None,
+    }?;
+    Some(minutes)
+        .filter(|&minutes| minutes <= MAX_PERIOD_MINS)
+        .map(Duration::minutes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn counts_votes_against_the_quorum() {
+        let now = Utc::now();
+        let mut vote =
+            Vote::open(&args("penalty warning no-action quorum=2 hidden"), 1, now).unwrap();
+        assert!(vote.hidden);
+        assert_eq!(now + Duration::hours(24), vote.deadline);
+
+        assert_eq!("penalty", vote.cast(10, "Penalty", now).unwrap());
+        assert!(matches!(
+            vote.cast(11, "reprimand", now),
+            Err(VoteError::UnknownChoice(..))
+        ));
+        assert_eq!(VoteResult::NoQuorum, vote.clone().close(None, now).result);
+
+        assert_eq!("warning", vote.cast(11, "2", now).unwrap());
+        assert_eq!(
+            VoteResult::Tied(vec!["penalty".to_string(), "warning".to_string()]),
+            vote.clone().close(None, now).result
+        );
+
+        // Stewards may change their minds
+        assert_eq!("penalty", vote.cast(11, "1", now).unwrap());
+        assert_eq!(
+            VoteResult::Decided("penalty".to_string()),
+            vote.close(Some(1), now).result
+        );
+        assert_eq!(Err(VoteError::NotOpen), vote.cast(12, "1", now));
+    }
+
+    #[test]
+    fn closes_at_the_deadline() {
+        let now = Utc::now();
+        let mut vote = Vote::open(&args("yes no deadline=30m"), 1, now).unwrap();
+        let later = now + Duration::minutes(30);
+        assert!(!vote.overdue(now));
+        assert!(vote.overdue(later));
+        assert_eq!(
+            Err(VoteError::PastDeadline(later)),
+            vote.cast(10, "yes", later)
+        );
+
+        assert_eq!(
+            VoteError::TooFewOptions,
+            Vote::open(&args("yes"), 1, now).unwrap_err()
+        );
+        assert_eq!(
+            VoteError::DuplicateOption("Yes".to_string()),
+            Vote::open(&args("yes Yes"), 1, now).unwrap_err()
+        );
+        assert!(matches!(
+            Vote::open(&args("yes no deadline=soon"), 1, now),
+            Err(VoteError::InvalidSetting(..))
+        ));
+        for deadline in &[
+            "deadline=99999999999d",
+            "deadline=367d",
+            "deadline=9223372036854775807m",
+        ] {
+            assert!(matches!(
+                Vote::open(&args(&format!("yes no {}", deadline)), 1, now),
+                Err(VoteError::InvalidSetting(..))
+            ));
+        }
+        assert_eq!(Some(Duration::days(366)), parse_period("366d"));
+    }
+}
@@ -0,0 +1,283 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+
+use crate::error::StrategyError;
+use crate::series::SeriesProfiles;
+use crate::strategy::{PaceSegment, PitWindow, SegmentStart};
+use crate::weather::WetPeriod;
+
+/// Arguments for a calculation, in the order they were given on the command line or in chat.
+///
+/// Positional arguments are read first, followed by any number of `key=value` options. Options
+/// may be written anywhere, e.g. `series=gtwc-endurance 3:00 2:18 3.4 120`, but keep their order
+/// relative to each other.
+#[derive(Debug, Clone)]
+pub struct Arguments {
+    args: Vec<String>,
+    position: usize,
+    /// Options applied ahead of those given, so that any given override them
+    defaults: Vec<(String, String)>,
+    /// Profiles which `series=<id>` options are replaced with
+    series: SeriesProfiles,
+}
+
+impl Arguments {
+    pub fn new<I, S>(args: I) -> Arguments
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let (mut args, options): (Vec<String>, Vec<String>) = args
+            .into_iter()
+            .map(Into::into)
+            .partition(|arg| !arg.contains('='));
+        args.extend(options);
+
+        Arguments {
+            args,
+            position: 0,
+            defaults: vec![],
+            series: SeriesProfiles::default(),
+        }
+    }
+
+    /// Apply `defaults` before any options given in the arguments themselves
+    pub fn with_defaults(mut self, defaults: Vec<(String, String)>) -> Arguments {
+        self.defaults = defaults;
+        self
+    }
+
+    /// Replace any `series=<id>` option with the options from the matching profile
+    pub fn with_series(mut self, series: SeriesProfiles) -> Arguments {
+        self.series = series;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.args.len()
+    }
+
+    /// Take the next positional argument, using `name` to describe it if it's missing
+    pub fn single(&mut self, name: &'static str) -> Result<String, StrategyError> {
+        let arg = self
+            .args
+            .get(self.position)
+            .cloned()
+            .ok_or(StrategyError::MissingArgument(name))?;
+        self.position += 1;
+        Ok(arg)
+    }
+
+    /// Take the next positional argument and parse it with `parse`
+    pub fn single_with<T, F>(&mut self, name: &'static str, parse: F) -> Result<T, StrategyError>
+    where
+        F: Fn(&str) -> Option<T>,
+    {
+        let arg = self.single(name)?;
+        parse(&arg).ok_or_else(|| StrategyError::invalid(name, &arg))
+    }
+
+    /// Take the next positional argument and parse it with `FromStr`
+    pub fn single_parsed<T: FromStr>(&mut self, name: &'static str) -> Result<T, StrategyError> {
+        self.single_with(name, |arg| arg.parse().ok())
+    }
+
+    /// Whether the next argument is positional, as optional positional arguments stop at the
+    /// first `key=value` option
+    pub fn has_positional(&self) -> bool {
+        self.args
+            .get(self.position)
+            .is_some_and(|arg| !arg.contains('='))
+    }
+
+    /// Take all of the remaining arguments as `key=value` options, after any defaults
+    pub fn options(&mut self) -> Result<Vec<(String, String)>, StrategyError> {
+        let mut options = self.defaults.clone();
+        while !self.is_empty() {
+            let arg = self.single("option")?;
+            match arg.split_once('=') {
+                Some((key, value)) if !key.is_empty() && !value.is_empty() => {
+                    options.push((key.to_string(), value.to_string()))
+                }
+                _ => return Err(StrategyError::UnknownOption(arg)),
+            }
+        }
+
+        // Expanded in place, so options given after a series override its rules
+        let mut expanded = vec![];
+        for (key, value) in options {
+            if key == "series" {
+                expanded.extend(self.series.options(&value)?);
+            } else {
+                expanded.push((key, value));
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+/// Parse an option value with `FromStr`, naming the option if it's invalid
+pub fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, StrategyError> {
+    value
+        .parse()
+        .map_err(|_| StrategyError::invalid(key, value))
+}
+
+// Accept either minutes or HH:MM
+pub fn parse_mins_or_hhmm(input: &str) -> Option<Duration> {
+    if !input.contains(':') {
+        let i = input.parse::<u32>().ok()?;
+        return Some(Duration::new(i as u64 * 60, 0));
+    } else {
+        let parts: Vec<&str> = input.split(':').collect();
+        if parts.len() == 2 {
+            let hours = parts[0].parse::<u32>().ok();
+            let minutes = parts[1].parse::<u32>().ok();
+
+            if let (Some(hours), Some(minutes)) = (hours, minutes) {
+                let mins: u64 = (hours * 60 + minutes) as u64;
+                return Some(Duration::new(mins * 60, 0));
+            }
+        }
+    }
+    None
+}
+
+pub fn parse_mmss(input: &str) -> Option<Duration> {
+    let parts: Vec<&str> = input.split(':').collect();
+    if parts.len() == 2 {
+        let mins = parts[0].parse::<u32>().ok();
+        let secs = parts[1].parse::<u32>().ok();
+
+        if let (Some(mins), Some(secs)) = (mins, secs) {
+            return Some(Duration::new((secs + mins * 60) as u64, 0));
+        }
+    }
+    None
+}
+
+// Accept an RFC 3339 time with offset, with or without seconds, or a Unix timestamp
+pub fn parse_start_time(input: &str) -> Option<DateTime<Utc>> {
+    if let Ok(timestamp) = input.parse::<i64>() {
+        return Utc.timestamp_opt(timestamp, 0).single();
+    }
+
+    DateTime::parse_from_rfc3339(input)
+        .or_else(|_| DateTime::parse_from_str(input, "%Y-%m-%dT%H:%M%:z"))
+        .map(|time| time.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            // A trailing Z is shorthand for UTC
+            let naive = input.strip_suffix('Z')?;
+            NaiveDateTime::parse_from_str(naive, "%Y-%m-%dT%H:%M")
+                .map(|time| DateTime::from_utc(time, Utc))
+                .ok()
+        })
+}
+
+// Accept `<start>@<lap time>/<fuel per lap>`, where the start is race time as minutes or HH:MM,
+// or a stint number prefixed with `s`
+pub fn parse_pace_segment(input: &str) -> Option<PaceSegment> {
+    let (start, pace) = input.split_once('@')?;
+    let (laptime, fuel_per_lap) = pace.split_once('/')?;
+
+    let start = if let Some(stint) = start.strip_prefix('s') {
+        match stint.parse::<usize>() {
+            Ok(stint) if stint > 0 => SegmentStart::Stint(stint - 1),
+            _ => return None,
+        }
+    } else {
+        SegmentStart::RaceTime(parse_mins_or_hhmm(start)?)
+    };
+
+    Some(PaceSegment {
+        start,
+        laptime: parse_mmss(laptime)?,
+        fuel_per_lap: fuel_per_lap.parse().ok()?,
+    })
+}
+
+/// Parse a period of race time as minutes or HH:MM, e.g. `0:25-1:05`
+fn parse_race_period(input: &str) -> Option<(Duration, Duration)> {
+    let (start, end) = input.split_once('-')?;
+    Some((parse_mins_or_hhmm(start)?, parse_mins_or_hhmm(end)?))
+}
+
+/// Parse a pit window in race time, e.g. `0:25-1:05`
+pub fn parse_pit_window(input: &str) -> Option<PitWindow> {
+    let (open, close) = parse_race_period(input)?;
+    Some(PitWindow { open, close })
+}
+
+/// Parse a period of race time forecast to be wet, e.g. `1:30-2:15`
+pub fn parse_wet_period(input: &str) -> Option<WetPeriod> {
+    let (start, end) = parse_race_period(input)?;
+    Some(WetPeriod { start, end })
+}
+
+/// Accept the ways people say yes or no
+pub fn parse_yes_no(input: &str) -> Option<bool> {
+    match input.to_lowercase().as_str() {
+        "yes" | "y" | "true" | "on" => Some(true),
+        "no" | "n" | "false" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_pace_segments() {
+        let segment = parse_pace_segment("8:00@2:24/3.6").unwrap();
+        assert_eq!(
+            SegmentStart::RaceTime(Duration::new(28800, 0)),
+            segment.start
+        );
+        assert_eq!(Duration::new(144, 0), segment.laptime);
+        assert_eq!(3.6, segment.fuel_per_lap);
+
+        let segment = parse_pace_segment("s3@2:20/3.5").unwrap();
+        assert_eq!(SegmentStart::Stint(2), segment.start);
+
+        assert!(parse_pace_segment("s0@2:20/3.5").is_none());
+        assert!(parse_pace_segment("2:20/3.5").is_none());
+    }
+
+    #[test]
+    fn parses_start_times() {
+        let expected = Utc.ymd(2021, 5, 1).and_hms(17, 0, 0);
+        assert_eq!(
+            Some(expected),
+            parse_start_time("2021-05-01T18:00:00+01:00")
+        );
+        assert_eq!(Some(expected), parse_start_time("2021-05-01T18:00+01:00"));
+        assert_eq!(Some(expected), parse_start_time("2021-05-01T17:00Z"));
+        assert_eq!(Some(expected), parse_start_time("1619888400"));
+        assert!(parse_start_time("18:00").is_none());
+        assert!(parse_start_time("99999999999999").is_none());
+        assert!(parse_start_time(&i64::MAX.to_string()).is_none());
+    }
+
+    #[test]
+    fn options_follow_positional_arguments() {
+        let mut args = Arguments::new(vec!["2:00", "1", "pace=s2@2:20/3.5", "weight=0.03"]);
+        assert_eq!("2:00", args.single("race length").unwrap());
+        assert!(args.has_positional());
+        assert_eq!(1, args.single_parsed::<u8>("mandatory pits").unwrap());
+        assert!(!args.has_positional());
+
+        let options = args.options().unwrap();
+        assert_eq!(2, options.len());
+        assert_eq!(("weight".to_string(), "0.03".to_string()), options[1]);
+        assert!(args.is_empty());
+
+        let mut args = Arguments::new(vec!["pace=s2@2:20/3.5", "2:00", "1", "weight=0.03"]);
+        assert_eq!("2:00", args.single("race length").unwrap());
+        assert_eq!(1, args.single_parsed::<u8>("mandatory pits").unwrap());
+        assert_eq!("pace", args.options().unwrap()[0].0);
+    }
+}
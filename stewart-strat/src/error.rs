@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StrategyError {
+    #[error("Missing argument: {0}")]
+    MissingArgument(&'static str),
+    #[error("Invalid value for {0}: {1}")]
+    InvalidArgument(String, String),
+    #[error("Unknown option: {0}")]
+    UnknownOption(String),
+    #[error("Invalid input: {0}")]
+    InvalidInput(&'static str),
+}
+
+impl StrategyError {
+    pub(crate) fn invalid(name: &str, value: &str) -> StrategyError {
+        StrategyError::InvalidArgument(name.to_string(), value.to_string())
+    }
+}
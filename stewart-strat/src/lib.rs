@@ -0,0 +1,26 @@
+//! Race strategy calculations for endurance racing: how long to run each stint, when to stop and
+//! how much fuel to add, along with safety car simulation and a solver for fuel consumption.
+//!
+//! Nothing here knows about Discord, so the same calculator backs both the bot and the
+//! `stewart-strat` command line tool.
+
+pub mod args;
+mod error;
+pub mod live;
+mod secs;
+pub mod series;
+pub mod simulation;
+pub mod solver;
+mod strategy;
+pub mod team;
+mod units;
+mod weather;
+
+pub use args::Arguments;
+pub use error::StrategyError;
+pub use strategy::{
+    format_energy, format_hms, round_secs, PaceSegment, PitWindow, SegmentStart, Stint, Stop,
+    Strategy, StrategyInner, StrategyInput, StrategyInputBuilder,
+};
+pub use units::{FuelUnit, FuelUnits};
+pub use weather::{Tyres, WetPeriod};
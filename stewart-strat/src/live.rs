@@ -0,0 +1,160 @@
+//! Re-planning the rest of a race from where the car actually is, for use with live telemetry.
+
+use std::time::Duration;
+
+use crate::strategy::{SegmentStart, StrategyInner, StrategyInput};
+
+/// Where the car is in the race, as reported by telemetry
+#[derive(Debug, Clone, Copy)]
+pub struct RaceState {
+    /// Laps completed
+    pub lap: u32,
+    /// Race time elapsed
+    pub elapsed: Duration,
+    /// Fuel on board
+    pub fuel: f64,
+    /// Stops made so far, which count towards any mandatory pits
+    pub stops_made: u8,
+}
+
+impl StrategyInput {
+    /// The input for the rest of the race from `elapsed`, with everything timed from the start of
+    /// the race moved to match
+    fn remainder(&self, elapsed: Duration, stops_made: u8) -> StrategyInput {
+        let shift = |time: Duration| time.saturating_sub(elapsed);
+        let mut rest = self.clone();
+        rest.race_duration = shift(self.race_duration);
+        rest.race_start = self
+            .race_start
+            .map(|start| start + chrono::Duration::milliseconds(elapsed.as_millis() as i64));
+        rest.mandatory_pits = self
+            .mandatory_pits
+            .map(|pits| pits.saturating_sub(stops_made));
+
+        for segment in &mut rest.pace_profile {
+            segment.start = match segment.start {
+                SegmentStart::RaceTime(start) => SegmentStart::RaceTime(shift(start)),
+                SegmentStart::Stint(stint) => {
+                    SegmentStart::Stint(stint.saturating_sub(stops_made as usize))
+                }
+            };
+        }
+        rest.pit_windows.retain(|window| window.close > elapsed);
+        for window in &mut rest.pit_windows {
+            window.open = shift(window.open);
+            window.close = shift(window.close);
+        }
+        rest.forecast.retain(|period| period.end > elapsed);
+        for period in &mut rest.forecast {
+            period.start = shift(period.start);
+            period.end = shift(period.end);
+        }
+        rest
+    }
+
+    /// Plan the rest of the race from `state`, running on the fuel on board until the first stop.
+    /// Laps in the plan are counted from the start of the race. Returns None once the race is
+    /// over, or too nearly over for another lap.
+    pub fn replan(&self, state: &RaceState) -> Option<StrategyInner> {
+        if state.elapsed >= self.race_duration {
+            return None;
+        }
+        let rest = self.remainder(state.elapsed, state.stops_made);
+        let planned = rest.calculate().into_iter().next()?;
+        let planned = planned.inner();
+
+        // The plan assumes the car is fuelled for its first stint, which it may not be
+        let on_board = (state.fuel.max(0.0) / rest.fuel_per_lap + 1e-9) as u32;
+        let mut stops: Vec<u32> = planned.stops.iter().map(|stop| stop.lap).collect();
+        if on_board < planned.stints.first()?.laps {
+            let first = rest.plan_with_stops(&[on_board]);
+            let after = rest.remainder(first.stints.first()?.duration, 1);
+            stops = std::iter::once(on_board)
+                .chain(
+                    after
+                        .calculate()
+                        .into_iter()
+                        .next()?
+                        .inner()
+                        .stops
+                        .iter()
+                        .map(|stop| on_board + stop.lap),
+                )
+                .collect();
+        }
+
+        let mut plan = rest.plan_with_stops(&stops);
+        for stop in &mut plan.stops {
+            stop.lap += state.lap;
+        }
+        Some(plan)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn input() -> StrategyInput {
+        // 30 laps to a tank and 60 laps in the race
+        StrategyInput::builder(
+            Duration::from_secs(2 * 3600),
+            Duration::from_secs(120),
+            3.0,
+            90.0,
+        )
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn keeps_the_plan_when_on_target() {
+        let plan = input()
+            .replan(&RaceState {
+                lap: 10,
+                elapsed: Duration::from_secs(1200),
+                fuel: 60.0,
+                stops_made: 0,
+            })
+            .unwrap();
+        assert_eq!(
+            vec![30],
+            plan.stops.iter().map(|s| s.lap).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn stops_early_when_fuel_runs_short() {
+        let plan = input()
+            .replan(&RaceState {
+                lap: 10,
+                elapsed: Duration::from_secs(1200),
+                fuel: 50.0,
+                stops_made: 0,
+            })
+            .unwrap();
+        assert_eq!(26, plan.stops[0].lap);
+        assert!(input().stints_within_limits(&plan.stints));
+        assert_eq!(
+            60,
+            10 + plan.stints.iter().map(|stint| stint.laps).sum::<u32>()
+        );
+
+        assert!(input()
+            .replan(&RaceState {
+                lap: 60,
+                elapsed: Duration::from_secs(7200),
+                fuel: 0.0,
+                stops_made: 1,
+            })
+            .is_none());
+        assert!(input()
+            .replan(&RaceState {
+                lap: 59,
+                elapsed: Duration::from_secs_f64(7199.5),
+                fuel: 3.0,
+                stops_made: 1,
+            })
+            .is_none());
+    }
+}
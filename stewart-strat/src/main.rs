@@ -0,0 +1,200 @@
+use std::error::Error;
+use std::fs;
+use std::process;
+
+use stewart_strat::series::SeriesProfiles;
+use stewart_strat::simulation::{self, SimulationParams};
+use stewart_strat::{format_hms, solver, team, Arguments, StrategyInput};
+
+const USAGE: &str = "\
+Usage:
+    stewart-strat [calc] <Race Length HH:MM or MMM> <Lap Time> <Fuel per Lap> <Fuel Capacity> [<Mandatory Pits> <Max Stint Length HH:MM>] [key=value...]
+    stewart-strat sim <same as calc> [runs=N] [sc=<Chance per Hour>] [sc_time=<Length MMM>] [sc_pace=<Multiplier>] [sc_fuel=<Multiplier>] [pit_loss=<Seconds>]
+    stewart-strat solve <Race Length HH:MM or MMM> <Lap Time> <Fuel Capacity> <Stops> [key=value...]   (solves for fuel per lap)
+    stewart-strat team <same as calc, for the first car> vs <same as calc, for the second car>
+
+Options:
+    pace=<Race Time HH:MM or Stint sN>@<Lap Time>/<Fuel per Lap>
+    weight=<Seconds per Unit of Fuel>
+    energy=<Energy per Lap>
+    energy_capacity=<Energy Capacity, 100 by default>
+    unit=<l, gal or kg>
+    refuel_unit=<l, gal or kg>
+    density=<kg per Litre>
+    refuel_step=<Refuel Increment>
+    start=<YYYY-MM-DDTHH:MM+HH:MM>
+    pit_time=<Seconds>
+    pits=<Mandatory Pits>
+    max_stint=<Max Stint Length HH:MM or MMM>
+    window=<Open HH:MM>-<Close HH:MM>
+    refuel=<yes or no>
+    rain=<Start HH:MM>-<End HH:MM>
+    wet_pace=<Lap Time on Wets in the Wet>
+    slicks_wet=<Lap Time on Slicks in the Wet>
+    wets_dry=<Lap Time on Wets in the Dry>
+    series=<Series ID>
+
+Flags:
+    --json             Print the result as JSON instead of a table
+    --series=<File>    Read series profiles from a file, series.json by default";
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let json = args.iter().any(|a| a == "--json");
+    args.retain(|a| a != "--json");
+
+    let series_file = args
+        .iter()
+        .find_map(|a| a.strip_prefix("--series="))
+        .map(String::from);
+    args.retain(|a| !a.starts_with("--series="));
+    let series = match load_series(series_file) {
+        Ok(series) => series,
+        Err(e) => {
+            eprintln!("Error reading series profiles: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let command = match args.first().map(String::as_str) {
+        Some("calc") | Some("sim") | Some("solve") | Some("team") => args.remove(0),
+        _ => "calc".to_string(),
+    };
+
+    let arguments = |args: Vec<String>| Arguments::new(args).with_series(series.clone());
+    let result = match command.as_str() {
+        "sim" => sim(&mut arguments(args), json),
+        "solve" => solve(&mut arguments(args), json),
+        "team" => team::split(args)
+            .map_err(Into::into)
+            .and_then(|(first, second)| {
+                plan_team(&mut arguments(first), &mut arguments(second), json)
+            }),
+        _ => calc(&mut arguments(args), json),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}\n\n{}", e, USAGE);
+        process::exit(1);
+    }
+}
+
+/// Read the given series file, or series.json if there is one
+fn load_series(path: Option<String>) -> Result<SeriesProfiles, Box<dyn Error>> {
+    let json = match path {
+        Some(path) => fs::read_to_string(path)?,
+        None => match fs::read_to_string("series.json") {
+            Ok(json) => json,
+            Err(_) => return Ok(SeriesProfiles::default()),
+        },
+    };
+    Ok(SeriesProfiles::from_json(&json)?)
+}
+
+fn calc(args: &mut Arguments, json: bool) -> Result<(), Box<dyn Error>> {
+    let strategies = StrategyInput::parse(args)?.calculate();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&strategies)?);
+    } else {
+        for strategy in strategies {
+            println!("{}\n{}\n", strategy.title(), strategy.inner().as_table());
+        }
+    }
+    Ok(())
+}
+
+fn sim(args: &mut Arguments, json: bool) -> Result<(), Box<dyn Error>> {
+    let (input, params) = SimulationParams::parse(args)?;
+    let outcomes = simulation::simulate(&input, &params);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&outcomes)?);
+    } else {
+        let ignored = simulation::ignored_options(&input);
+        if !ignored.is_empty() {
+            println!(
+                "Laps are simulated at the average lap time, ignoring {}.\n",
+                ignored.join(", ")
+            );
+        }
+        for outcome in outcomes {
+            println!(
+                "{}\n  Expected finish:       {}\n  Pitting under caution: {}\n  Opportunistic wins:    {:.0}%\n",
+                outcome.strategy.title(),
+                format_hms(outcome.expected_finish),
+                format_hms(outcome.expected_opportunistic_finish),
+                outcome.opportunistic_win_rate * 100.0
+            );
+        }
+    }
+    Ok(())
+}
+
+fn solve(args: &mut Arguments, json: bool) -> Result<(), Box<dyn Error>> {
+    let (input, target_stops) = solver::parse(args)?;
+    let solution = solver::solve_fuel_per_lap(&input, target_stops);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&solution)?);
+    } else if let Some(solution) = solution {
+        println!(
+            "Up to {:.2} {} per lap finishes on {} stops, running {} laps on a full tank",
+            solution.fuel_per_lap,
+            input.fuel_units.unit.symbol(),
+            target_stops,
+            solution.stint_laps
+        );
+    } else {
+        println!(
+            "There's no way to finish on {} stops, the regulations require more",
+            target_stops
+        );
+    }
+    Ok(())
+}
+
+fn plan_team(
+    first: &mut Arguments,
+    second: &mut Arguments,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    let first = StrategyInput::parse(first)?;
+    let second = StrategyInput::parse(second)?;
+    let plan = team::plan_team(&first, &second);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&plan)?);
+        return Ok(());
+    }
+
+    for (i, car) in plan.cars.iter().enumerate() {
+        println!(
+            "Car {}: {}\n{}\n",
+            i + 1,
+            car.title(),
+            car.inner().as_table()
+        );
+    }
+    for moved in &plan.moved_stops {
+        println!(
+            "Car 2 stops on lap {} instead of {}",
+            moved.lap, moved.planned_lap
+        );
+    }
+    for conflict in &plan.conflicts {
+        println!(
+            "Both cars need the box on laps {} and {}, costing {}",
+            conflict.laps[0],
+            conflict.laps[1],
+            format_hms(conflict.cost)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::error::StrategyError;
+
+/// Rules for a championship, as the options a user would otherwise have to remember to give
+#[derive(Debug, Clone, Deserialize)]
+pub struct SeriesProfile {
+    /// Full name of the series, for showing to users
+    pub name: String,
+    /// `key=value` options applied wherever `series=<id>` is given, e.g. `pits=2`
+    pub options: Vec<String>,
+}
+
+/// Every series profile we know about, by the ID used to select it. IDs are kept in lower case
+/// so they can be given in any case.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "BTreeMap<String, SeriesProfile>")]
+pub struct SeriesProfiles(BTreeMap<String, SeriesProfile>);
+
+impl From<BTreeMap<String, SeriesProfile>> for SeriesProfiles {
+    fn from(profiles: BTreeMap<String, SeriesProfile>) -> SeriesProfiles {
+        SeriesProfiles(
+            profiles
+                .into_iter()
+                .map(|(id, profile)| (id.to_lowercase(), profile))
+                .collect(),
+        )
+    }
+}
+
+impl SeriesProfiles {
+    /// Read profiles from JSON, keyed by ID, e.g.
+    /// `{"gtwc-endurance": {"name": "GT World Challenge Endurance", "options": ["pits=2"]}}`
+    pub fn from_json(json: &str) -> serde_json::Result<SeriesProfiles> {
+        serde_json::from_str(json)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&SeriesProfile> {
+        self.0.get(&id.to_lowercase())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &SeriesProfile)> {
+        self.0.iter()
+    }
+
+    /// The options making up the profile `id`
+    pub(crate) fn options(&self, id: &str) -> Result<Vec<(String, String)>, StrategyError> {
+        let profile = self
+            .get(id)
+            .ok_or_else(|| StrategyError::invalid("series", id))?;
+        profile
+            .options
+            .iter()
+            .map(|option| match option.split_once('=') {
+                Some((key, value)) if key != "series" => Ok((key.to_string(), value.to_string())),
+                _ => Err(StrategyError::invalid("series", id)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Arguments, StrategyInput};
+
+    #[test]
+    fn series_options_apply_before_later_ones() {
+        let profiles = SeriesProfiles::from_json(
+            r#"{"GTWC-Endurance": {"name": "GT World Challenge Endurance", "options": ["pits=2", "max_stint=65", "window=0:25-1:05"]}}"#,
+        )
+        .unwrap();
+        assert!(profiles.get("gtwc-endurance").is_some());
+        assert_eq!(
+            vec!["gtwc-endurance"],
+            profiles
+                .iter()
+                .map(|(id, _)| id.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let mut args = Arguments::new(vec![
+            "3:00",
+            "2:18",
+            "3.4",
+            "120",
+            "series=GTWC-Endurance",
+            "pits=3",
+        ])
+        .with_series(profiles.clone());
+        let input = StrategyInput::parse(&mut args).unwrap();
+        assert_eq!(Some(3), input.mandatory_pits);
+        assert_eq!(1, input.pit_windows.len());
+        assert_eq!(
+            Some(3900),
+            input.permitted_max_stint_length.map(|d| d.as_secs())
+        );
+
+        let mut args = Arguments::new(vec!["3:00", "2:18", "3.4", "120", "series=unknown"])
+            .with_series(profiles);
+        assert!(StrategyInput::parse(&mut args).is_err());
+    }
+}
@@ -0,0 +1,373 @@
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+
+use crate::args::{parse_mins_or_hhmm, parse_value, Arguments};
+use crate::error::StrategyError;
+use crate::secs;
+use crate::strategy::{Strategy, StrategyInput};
+
+/// Most randomised races a single simulation may run
+pub const MAX_RUNS: u32 = 50_000;
+
+/// Parameters controlling the randomised races run by [`simulate`]
+#[derive(Debug, Clone)]
+pub struct SimulationParams {
+    /// How many randomised races to run
+    pub runs: u32,
+    /// Chance of a safety car being deployed in any one hour of racing
+    pub safety_car_chance: f64,
+    /// How long each safety car period lasts
+    pub safety_car_duration: Duration,
+    /// Lap time under caution as a multiple of the green flag lap time
+    pub caution_pace: f64,
+    /// Fuel used per lap under caution as a fraction of green flag consumption
+    pub caution_fuel: f64,
+    /// Time lost in the pit lane for a stop under green flag conditions
+    pub pit_loss: Duration,
+    pub seed: Option<u64>,
+}
+
+impl Default for SimulationParams {
+    fn default() -> Self {
+        SimulationParams {
+            runs: 5000,
+            safety_car_chance: 0.5,
+            safety_car_duration: Duration::new(300, 0),
+            caution_pace: 1.4,
+            caution_fuel: 0.5,
+            pit_loss: Duration::new(60, 0),
+            seed: None,
+        }
+    }
+}
+
+impl SimulationParams {
+    /// Read the race and simulation parameters, falling back to defaults for any options that
+    /// weren't given
+    pub fn parse(args: &mut Arguments) -> Result<(StrategyInput, SimulationParams), StrategyError> {
+        let mut input = StrategyInput::parse_positional(args)?;
+        let mut params = SimulationParams::default();
+
+        for (key, value) in args.options()? {
+            if !input.apply_option(&key, &value)? && !params.apply_option(&key, &value)? {
+                return Err(StrategyError::UnknownOption(key));
+            }
+        }
+
+        input.validate()?;
+        params.validate()?;
+        Ok((input, params))
+    }
+
+    fn apply_option(&mut self, key: &str, value: &str) -> Result<bool, StrategyError> {
+        match key {
+            "runs" => self.runs = parse_value(key, value)?,
+            "sc" => self.safety_car_chance = parse_value(key, value)?,
+            "sc_time" => {
+                self.safety_car_duration =
+                    parse_mins_or_hhmm(value).ok_or_else(|| StrategyError::invalid(key, value))?
+            }
+            "sc_pace" => self.caution_pace = parse_value(key, value)?,
+            "sc_fuel" => self.caution_fuel = parse_value(key, value)?,
+            "pit_loss" => self.pit_loss = Duration::new(parse_value(key, value)?, 0),
+            "seed" => self.seed = Some(parse_value(key, value)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    pub fn validate(&self) -> Result<(), StrategyError> {
+        if self.runs == 0 {
+            return Err(StrategyError::InvalidInput("at least one run is needed"));
+        }
+        if self.runs > MAX_RUNS {
+            return Err(StrategyError::InvalidInput(
+                "no more than 50000 runs may be simulated",
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.safety_car_chance) {
+            return Err(StrategyError::InvalidInput(
+                "safety car chance must be between 0 and 1",
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.caution_fuel) {
+            return Err(StrategyError::InvalidInput(
+                "fuel use under caution must be between 0 and 1",
+            ));
+        }
+        if self.caution_pace.is_nan() || self.caution_pace < 1.0 {
+            return Err(StrategyError::InvalidInput(
+                "lap times under caution can't be quicker than racing",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationOutcome {
+    pub strategy: Strategy,
+    /// Mean finishing time when sticking to the planned stops
+    #[serde(with = "secs")]
+    pub expected_finish: Duration,
+    /// Mean finishing time when bringing stops forward under caution
+    #[serde(with = "secs")]
+    pub expected_opportunistic_finish: Duration,
+    /// Fraction of races in which the opportunistic stop finished ahead of the plan
+    pub opportunistic_win_rate: f64,
+}
+
+struct Race<'a> {
+    input: &'a StrategyInput,
+    params: &'a SimulationParams,
+    /// Whether each lap of the race is run under caution
+    cautions: Vec<bool>,
+}
+
+impl<'a> Race<'a> {
+    fn generate(input: &'a StrategyInput, params: &'a SimulationParams, rng: &mut StdRng) -> Self {
+        let laps = input.race_laps() as usize;
+        let laptime = input.avg_laptime.as_secs_f64();
+
+        // Convert the hourly deployment chance into a per-lap chance
+        let lap_chance = 1.0 - (1.0 - params.safety_car_chance).powf(laptime / 3600.0);
+        let caution_laps = (params.safety_car_duration.as_secs_f64()
+            / (laptime * params.caution_pace))
+            .ceil()
+            .max(1.0) as usize;
+
+        let mut cautions = vec![false; laps];
+        let mut lap = 0;
+        while lap < laps {
+            if rng.gen_bool(lap_chance) {
+                let end = std::cmp::min(lap + caution_laps, laps);
+                cautions[lap..end].iter_mut().for_each(|c| *c = true);
+                lap = end;
+            } else {
+                lap += 1;
+            }
+        }
+
+        Race {
+            input,
+            params,
+            cautions,
+        }
+    }
+
+    fn lap_time(&self, lap: u32) -> Duration {
+        if self.cautions[lap as usize - 1] {
+            self.input.avg_laptime.mul_f64(self.params.caution_pace)
+        } else {
+            self.input.avg_laptime
+        }
+    }
+
+    // Under caution the rest of the field is slow too, so the stop costs less
+    fn pit_loss(&self, lap: u32) -> Duration {
+        if self.cautions[lap as usize - 1] {
+            let slowdown = self.lap_time(lap) - self.input.avg_laptime;
+            self.params
+                .pit_loss
+                .checked_sub(slowdown)
+                .unwrap_or_else(|| Duration::new(0, 0))
+        } else {
+            self.params.pit_loss
+        }
+    }
+
+    /// Finishing time when stopping on exactly the planned laps
+    fn run_planned(&self, stops: &[u32]) -> Duration {
+        let mut elapsed = Duration::new(0, 0);
+        for lap in 1..=self.cautions.len() as u32 {
+            elapsed += self.lap_time(lap);
+            if stops.contains(&lap) {
+                elapsed += self.pit_loss(lap);
+            }
+        }
+        elapsed
+    }
+
+    /// Finishing time when taking the next stop early whenever a caution makes it possible
+    fn run_opportunistic(&self, stops: &[u32]) -> Duration {
+        let race_laps = self.cautions.len() as u32;
+        let max_stint_laps = self.input.max_stint_laps();
+        let mandatory_pits = self.input.mandatory_pits.unwrap_or(0) as u32;
+
+        let mut planned: Vec<u32> = stops.to_vec();
+        let mut stops_taken = 0;
+        // Fuel on board, measured in green flag laps
+        let mut range = planned.first().copied().unwrap_or(race_laps) as f64;
+        let mut elapsed = Duration::new(0, 0);
+        for lap in 1..=race_laps {
+            let caution = self.cautions[lap as usize - 1];
+            elapsed += self.lap_time(lap);
+            range -= if caution {
+                self.params.caution_fuel
+            } else {
+                1.0
+            };
+            let laps_left = race_laps - lap;
+
+            if planned.first() == Some(&lap) {
+                planned.remove(0);
+                // Fuel saved under caution can make the final stop unnecessary
+                if planned.is_empty() && stops_taken >= mandatory_pits && range >= laps_left as f64
+                {
+                    continue;
+                }
+                elapsed += self.pit_loss(lap);
+                stops_taken += 1;
+                range = (planned.first().copied().unwrap_or(race_laps) - lap) as f64;
+                continue;
+            }
+
+            let stops_left = planned.len() as u32;
+            // Pitting now is only possible if the remaining stops can still cover the distance
+            if caution && stops_left > 0 && laps_left <= stops_left * max_stint_laps {
+                elapsed += self.pit_loss(lap);
+                stops_taken += 1;
+
+                // Spread the remaining stops evenly over the rest of the race
+                let remaining = stops_left - 1;
+                planned = (1..=remaining)
+                    .map(|i| lap + (laps_left * i + remaining) / (remaining + 1))
+                    .collect();
+                range = (planned.first().copied().unwrap_or(race_laps) - lap) as f64;
+            }
+        }
+        elapsed
+    }
+}
+
+/// Options set on `input` which the simulation doesn't model. Every lap is run at the average
+/// lap time.
+pub fn ignored_options(input: &StrategyInput) -> Vec<&'static str> {
+    let mut ignored = vec![];
+    if !input.pace_profile.is_empty() {
+        ignored.push("pace");
+    }
+    if input.fuel_weight_penalty.is_some() {
+        ignored.push("weight");
+    }
+    if !input.forecast.is_empty() {
+        ignored.push("forecast");
+    }
+    ignored
+}
+
+/// Run randomised races with safety car periods for each strategy calculated from `input`
+pub fn simulate(input: &StrategyInput, params: &SimulationParams) -> Vec<SimulationOutcome> {
+    let mut rng = match params.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let strategies = input.calculate();
+    let stops: Vec<Vec<u32>> = strategies
+        .iter()
+        .map(|s| s.inner().stops.iter().map(|stop| stop.lap).collect())
+        .collect();
+
+    let mut planned_total = vec![0.0; strategies.len()];
+    let mut opportunistic_total = vec![0.0; strategies.len()];
+    let mut opportunistic_wins = vec![0u32; strategies.len()];
+
+    // Every strategy runs the same randomised race so they can be compared fairly
+    for _ in 0..params.runs {
+        let race = Race::generate(input, params, &mut rng);
+        for (i, stops) in stops.iter().enumerate() {
+            let planned = race.run_planned(stops);
+            let opportunistic = race.run_opportunistic(stops);
+
+            planned_total[i] += planned.as_secs_f64();
+            opportunistic_total[i] += opportunistic.as_secs_f64();
+            if opportunistic < planned {
+                opportunistic_wins[i] += 1;
+            }
+        }
+    }
+
+    let runs = params.runs as f64;
+    strategies
+        .into_iter()
+        .enumerate()
+        .map(|(i, strategy)| SimulationOutcome {
+            strategy,
+            expected_finish: Duration::from_secs_f64(planned_total[i] / runs),
+            expected_opportunistic_finish: Duration::from_secs_f64(opportunistic_total[i] / runs),
+            opportunistic_win_rate: opportunistic_wins[i] as f64 / runs,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn input() -> StrategyInput {
+        StrategyInput::builder(
+            Duration::new(14400, 0), // 4 hrs
+            Duration::new(138, 0),   // 2:18
+            3.90,
+            110.0,
+        )
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn no_safety_cars_matches_plan() {
+        let params = SimulationParams {
+            runs: 10,
+            safety_car_chance: 0.0,
+            seed: Some(1),
+            ..Default::default()
+        };
+
+        let outcomes = simulate(&input(), &params);
+        assert_eq!(2, outcomes.len());
+        for outcome in outcomes {
+            let stops = outcome.strategy.inner().stops.len() as u64;
+            // 105 laps at 2:18 plus a minute for every stop
+            assert_eq!(
+                Duration::new(105 * 138 + stops * 60, 0),
+                outcome.expected_finish
+            );
+            assert_eq!(
+                outcome.expected_finish,
+                outcome.expected_opportunistic_finish
+            );
+            assert_eq!(0.0, outcome.opportunistic_win_rate);
+        }
+    }
+
+    #[test]
+    fn pitting_under_caution_pays_off() {
+        let params = SimulationParams {
+            runs: 500,
+            safety_car_chance: 0.8,
+            seed: Some(7),
+            ..Default::default()
+        };
+
+        for outcome in simulate(&input(), &params) {
+            assert!(outcome.opportunistic_win_rate > 0.0);
+            assert!(outcome.expected_opportunistic_finish < outcome.expected_finish);
+        }
+    }
+
+    #[test]
+    fn limits_runs() {
+        let params = |runs| SimulationParams {
+            runs,
+            ..Default::default()
+        };
+        assert!(params(MAX_RUNS).validate().is_ok());
+        assert!(params(0).validate().is_err());
+        assert!(params(MAX_RUNS + 1).validate().is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use serde::Serialize;
+
+use crate::args::{parse_mins_or_hhmm, parse_mmss, Arguments};
+use crate::error::StrategyError;
+use crate::strategy::StrategyInput;
+
+/// The highest fuel consumption which still lets a race be run on a target number of stops
+#[derive(Debug, Clone, Serialize)]
+pub struct Solution {
+    pub fuel_per_lap: f64,
+    /// Laps which can be run on a full tank at that consumption
+    pub stint_laps: u32,
+}
+
+/// Read the race, lap time, fuel capacity and target number of stops for the solver
+pub fn parse(args: &mut Arguments) -> Result<(StrategyInput, u8), StrategyError> {
+    // Ordering must be preserved
+    let race_time = args.single_with("race length", parse_mins_or_hhmm)?;
+    let lap_time = args.single_with("lap time", parse_mmss)?;
+    let fuel_capacity = args.single_parsed::<f64>("fuel capacity")?;
+    let target_stops = args.single_parsed::<u8>("stops")?;
+    // End preserve ordering
+
+    let mut input =
+        StrategyInput::builder(race_time, lap_time, fuel_capacity, fuel_capacity).build()?;
+    for (key, value) in args.options()? {
+        if !input.apply_option(&key, &value)? {
+            return Err(StrategyError::UnknownOption(key));
+        }
+    }
+
+    // Check the rest of the input at the most frugal consumption the solver could pick, a single
+    // tank for the whole race, so options such as `refuel=no` aren't judged on a thirsty car
+    input.fuel_per_lap = stint_fuel_per_lap(input.fuel_capacity, input.race_laps().max(1));
+    input.validate()?;
+    Ok((input, target_stops))
+}
+
+/// Find the most fuel per lap the car can use and still need no more than `target_stops` stops.
+/// Only consumption is solved for, the lap time is taken as given.
+///
+/// Consumption is only solved for the average pace, so any segments in the pace profile keep
+/// their own fuel per lap. Returns `None` if no consumption is low enough, for example when the
+/// regulations alone require more stops.
+pub fn solve_fuel_per_lap(input: &StrategyInput, target_stops: u8) -> Option<Solution> {
+    let target_stints = target_stops.checked_add(1)?;
+    let mut candidate = input.clone();
+
+    // Fewer laps per tank means a thirstier car, so the first workable stint length is the answer
+    for stint_laps in 1..=input.race_laps() {
+        let fuel_per_lap = stint_fuel_per_lap(input.fuel_capacity, stint_laps);
+        if fuel_per_lap <= 0.0 {
+            break;
+        }
+
+        candidate.fuel_per_lap = fuel_per_lap;
+        // The rules may still rule it out, as when refuelling is banned
+        if candidate.required_stints() <= target_stints && candidate.validate().is_ok() {
+            return Some(Solution {
+                fuel_per_lap,
+                stint_laps: (input.fuel_capacity / fuel_per_lap) as u32,
+            });
+        }
+    }
+
+    None
+}
+
+/// Fuel per lap to run `stint_laps` on a full tank, rounded down to something a driver can aim
+/// for which still gives enough laps per tank
+fn stint_fuel_per_lap(fuel_capacity: f64, stint_laps: u32) -> f64 {
+    (fuel_capacity / stint_laps as f64 * 100.0).floor() / 100.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn input() -> StrategyInput {
+        StrategyInput::builder(
+            Duration::new(14400, 0), // 4 hrs
+            Duration::new(138, 0),   // 2:18
+            110.0,
+            110.0,
+        )
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn solves_for_three_stops() {
+        let solution = solve_fuel_per_lap(&input(), 3).unwrap();
+        assert_eq!(4.07, solution.fuel_per_lap);
+        assert_eq!(27, solution.stint_laps);
+
+        // Anything thirstier needs another stop
+        let mut thirstier = input();
+        thirstier.fuel_per_lap = 4.08;
+        assert_eq!(5, thirstier.required_stints());
+    }
+
+    #[test]
+    fn solves_without_refuelling() {
+        let mut args = Arguments::new(vec!["1:00", "2:18", "110", "1", "refuel=no"]);
+        let (input, target_stops) = parse(&mut args).unwrap();
+        assert!(input.refuelling_banned);
+
+        // 27 laps on the one tank, however many stops are allowed
+        let solution = solve_fuel_per_lap(&input, target_stops).unwrap();
+        assert_eq!(4.07, solution.fuel_per_lap);
+        assert_eq!(27, solution.stint_laps);
+    }
+
+    #[test]
+    fn no_solution_when_regulations_require_more_stops() {
+        let mut input = input();
+        input.permitted_max_stint_length = Some(Duration::new(3600, 0));
+        assert!(solve_fuel_per_lap(&input, 2).is_none());
+        assert!(solve_fuel_per_lap(&input, 3).is_some());
+    }
+}
@@ -0,0 +1,1554 @@
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::args::{
+    parse_mins_or_hhmm, parse_mmss, parse_pace_segment, parse_pit_window, parse_start_time,
+    parse_value, parse_wet_period, parse_yes_no, Arguments,
+};
+use crate::error::StrategyError;
+use crate::secs;
+use crate::units::{FuelUnit, FuelUnits};
+use crate::weather::{Tyres, WetPeriod};
+
+/// Virtual energy is usually measured as a percentage of the tank
+const DEFAULT_ENERGY_CAPACITY: f64 = 100.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "strategy")]
+pub enum Strategy {
+    SingleStint(StrategyInner),
+    LongStints(StrategyInner),
+    EqualStints(StrategyInner),
+    PitWindows(StrategyInner),
+    Weather(StrategyInner),
+}
+
+impl Strategy {
+    pub fn inner(&self) -> &StrategyInner {
+        match self {
+            Strategy::SingleStint(inner) => inner,
+            Strategy::LongStints(inner) => inner,
+            Strategy::EqualStints(inner) => inner,
+            Strategy::PitWindows(inner) => inner,
+            Strategy::Weather(inner) => inner,
+        }
+    }
+
+    pub(crate) fn inner_mut(&mut self) -> &mut StrategyInner {
+        match self {
+            Strategy::SingleStint(inner) => inner,
+            Strategy::LongStints(inner) => inner,
+            Strategy::EqualStints(inner) => inner,
+            Strategy::PitWindows(inner) => inner,
+            Strategy::Weather(inner) => inner,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        match self {
+            Strategy::SingleStint(_) => "Single Stint",
+            Strategy::LongStints(_) => "Longer Stints",
+            Strategy::EqualStints(_) => "Equal Stints",
+            Strategy::PitWindows(_) => "Pit Windows",
+            Strategy::Weather(_) => "Weather",
+        }
+    }
+}
+
+impl StrategyInput {
+    /// Start building an input from the values every calculation needs
+    pub fn builder(
+        race_duration: Duration,
+        avg_laptime: Duration,
+        fuel_per_lap: f64,
+        fuel_capacity: f64,
+    ) -> StrategyInputBuilder {
+        StrategyInputBuilder {
+            input: StrategyInput {
+                race_duration,
+                avg_laptime,
+                fuel_per_lap,
+                fuel_capacity,
+                mandatory_pits: None,
+                permitted_max_stint_length: None,
+                pace_profile: vec![],
+                fuel_weight_penalty: None,
+                race_start: None,
+                pit_stop_duration: None,
+                fuel_units: FuelUnits::default(),
+                energy_capacity: None,
+                energy_per_lap: None,
+                pit_windows: vec![],
+                refuelling_banned: false,
+                forecast: vec![],
+                wet_laptime: None,
+                slicks_wet_laptime: None,
+                wets_dry_laptime: None,
+            },
+        }
+    }
+
+    /// Read the positional arguments followed by any `key=value` options
+    pub fn parse(args: &mut Arguments) -> Result<StrategyInput, StrategyError> {
+        let mut input = StrategyInput::parse_positional(args)?;
+        for (key, value) in args.options()? {
+            if !input.apply_option(&key, &value)? {
+                return Err(StrategyError::UnknownOption(key));
+            }
+        }
+
+        input.validate()?;
+        Ok(input)
+    }
+
+    /// Read only the positional arguments, leaving any options to the caller
+    pub fn parse_positional(args: &mut Arguments) -> Result<StrategyInput, StrategyError> {
+        // Ordering must be preserved
+        let race_time = args.single_with("race length", parse_mins_or_hhmm)?;
+        let lap_time = args.single_with("lap time", parse_mmss)?;
+        let fuel_per_lap = args.single_parsed::<f64>("fuel per lap")?;
+        let fuel_capacity = args.single_parsed::<f64>("fuel capacity")?;
+        let mut builder = StrategyInput::builder(race_time, lap_time, fuel_per_lap, fuel_capacity);
+
+        // Optional args, mandatory pitstops and max stint time
+        if args.has_positional() {
+            builder = builder.mandatory_pits(args.single_parsed("mandatory pits")?);
+        }
+
+        if args.has_positional() {
+            builder =
+                builder.max_stint_length(args.single_with("max stint length", parse_mins_or_hhmm)?);
+        }
+        // End preserve ordering
+
+        builder.build()
+    }
+
+    /// Apply a `key=value` option to the input, returning false if the key isn't one of ours
+    pub fn apply_option(&mut self, key: &str, value: &str) -> Result<bool, StrategyError> {
+        let invalid = || StrategyError::invalid(key, value);
+        match key {
+            "pace" => self
+                .pace_profile
+                .push(parse_pace_segment(value).ok_or_else(invalid)?),
+            "weight" => self.fuel_weight_penalty = Some(parse_value(key, value)?),
+            "start" => self.race_start = Some(parse_start_time(value).ok_or_else(invalid)?),
+            "pit_time" => self.pit_stop_duration = Some(Duration::new(parse_value(key, value)?, 0)),
+            "unit" => {
+                let unit = FuelUnit::parse(value).ok_or_else(invalid)?;
+                self.fuel_units.unit = unit;
+                self.fuel_units.refuel_unit = unit;
+            }
+            "refuel_unit" => {
+                self.fuel_units.refuel_unit = FuelUnit::parse(value).ok_or_else(invalid)?
+            }
+            "pits" => self.mandatory_pits = Some(parse_value(key, value)?),
+            "max_stint" => {
+                self.permitted_max_stint_length =
+                    Some(parse_mins_or_hhmm(value).ok_or_else(invalid)?)
+            }
+            "window" => self
+                .pit_windows
+                .push(parse_pit_window(value).ok_or_else(invalid)?),
+            "refuel" => self.refuelling_banned = !parse_yes_no(value).ok_or_else(invalid)?,
+            "energy" => self.energy_per_lap = Some(parse_value(key, value)?),
+            "energy_capacity" => self.energy_capacity = Some(parse_value(key, value)?),
+            "density" => self.fuel_units.density = parse_value(key, value)?,
+            "refuel_step" => self.fuel_units.refuel_increment = parse_value(key, value)?,
+            "rain" => self
+                .forecast
+                .push(parse_wet_period(value).ok_or_else(invalid)?),
+            "wet_pace" => self.wet_laptime = Some(parse_mmss(value).ok_or_else(invalid)?),
+            "slicks_wet" => self.slicks_wet_laptime = Some(parse_mmss(value).ok_or_else(invalid)?),
+            "wets_dry" => self.wets_dry_laptime = Some(parse_mmss(value).ok_or_else(invalid)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Check the input describes a race we can actually calculate a strategy for
+    pub fn validate(&self) -> Result<(), StrategyError> {
+        if self.race_duration.as_secs() == 0 {
+            return Err(StrategyError::InvalidInput(
+                "race length must be more than zero",
+            ));
+        }
+        if !self.fuel_capacity.is_finite() || self.fuel_capacity <= 0.0 {
+            return Err(StrategyError::InvalidInput(
+                "fuel capacity must be more than zero",
+            ));
+        }
+
+        if self
+            .fuel_weight_penalty
+            .is_some_and(|penalty| !penalty.is_finite() || penalty < 0.0)
+        {
+            return Err(StrategyError::InvalidInput(
+                "fuel weight penalty can't be negative",
+            ));
+        }
+
+        let paces = std::iter::once(self.base_pace()).chain(
+            self.pace_profile
+                .iter()
+                .map(|segment| self.pace_at_segment(segment)),
+        );
+        for pace in paces {
+            if pace.laptime.as_secs() == 0 {
+                return Err(StrategyError::InvalidInput(
+                    "lap time must be more than zero",
+                ));
+            }
+            if !pace.fuel_per_lap.is_finite() || pace.fuel_per_lap <= 0.0 {
+                return Err(StrategyError::InvalidInput(
+                    "fuel per lap must be more than zero",
+                ));
+            }
+            if pace.fuel_per_lap > self.fuel_capacity {
+                return Err(StrategyError::InvalidInput(
+                    "fuel capacity must be enough for at least one lap",
+                ));
+            }
+            // Stints only get longer with more laps, so if the longest one we could plan
+            // has a length, so do all the others
+            let tank_laps = (self.fuel_capacity / pace.fuel_per_lap) as u32;
+            let race_laps =
+                (self.race_duration.as_secs_f64() / pace.laptime.as_secs_f64()).ceil() as u32;
+            if pace.checked_duration(tank_laps.max(race_laps)).is_none() {
+                return Err(StrategyError::InvalidInput(
+                    "stints would be too long to plan, try a smaller fuel capacity or weight penalty",
+                ));
+            }
+        }
+
+        if self.permitted_max_stint_length.map(|max| max.as_secs()) == Some(0) {
+            return Err(StrategyError::InvalidInput(
+                "max stint length must be more than zero",
+            ));
+        }
+        if let Some(energy_per_lap) = self.energy_per_lap {
+            let capacity = self.energy_capacity();
+            if !capacity.is_finite() || capacity <= 0.0 {
+                return Err(StrategyError::InvalidInput(
+                    "energy capacity must be more than zero",
+                ));
+            }
+            if !energy_per_lap.is_finite() || energy_per_lap <= 0.0 {
+                return Err(StrategyError::InvalidInput(
+                    "energy per lap must be more than zero",
+                ));
+            }
+            if energy_per_lap > capacity {
+                return Err(StrategyError::InvalidInput(
+                    "energy capacity must be enough for at least one lap",
+                ));
+            }
+        }
+        if self
+            .pit_windows
+            .iter()
+            .any(|window| window.open >= window.close)
+        {
+            return Err(StrategyError::InvalidInput(
+                "pit windows must close after they open",
+            ));
+        }
+        if self
+            .pit_windows
+            .windows(2)
+            .any(|pair| pair[1].open < pair[0].close)
+        {
+            return Err(StrategyError::InvalidInput(
+                "pit windows must be in order and not overlap",
+            ));
+        }
+        if !self.forecast.is_empty() {
+            if self.wet_laptime.is_none() {
+                return Err(StrategyError::InvalidInput(
+                    "a forecast needs a lap time on wets, e.g. wet_pace=2:40",
+                ));
+            }
+            let laptimes = [
+                self.wet_laptime,
+                self.slicks_wet_laptime,
+                self.wets_dry_laptime,
+            ];
+            if laptimes
+                .iter()
+                .flatten()
+                .any(|laptime| laptime.as_secs() == 0)
+            {
+                return Err(StrategyError::InvalidInput(
+                    "lap time must be more than zero",
+                ));
+            }
+            if self
+                .forecast
+                .iter()
+                .any(|period| period.start >= period.end)
+            {
+                return Err(StrategyError::InvalidInput(
+                    "rain must stop after it starts",
+                ));
+            }
+            if !self.pit_windows.is_empty() {
+                return Err(StrategyError::InvalidInput(
+                    "pit windows can't be planned around a forecast yet",
+                ));
+            }
+        }
+        if self.refuelling_banned && self.fuel_required_stints() > 1 {
+            return Err(StrategyError::InvalidInput(
+                "fuel capacity must last the whole race when refuelling is banned",
+            ));
+        }
+        self.fuel_units
+            .validate()
+            .map_err(StrategyError::InvalidInput)?;
+
+        Ok(())
+    }
+}
+
+/// Builds a [`StrategyInput`], for callers who aren't parsing arguments
+#[derive(Debug, Clone)]
+pub struct StrategyInputBuilder {
+    input: StrategyInput,
+}
+
+impl StrategyInputBuilder {
+    pub fn mandatory_pits(mut self, pits: u8) -> Self {
+        self.input.mandatory_pits = Some(pits);
+        self
+    }
+
+    pub fn max_stint_length(mut self, length: Duration) -> Self {
+        self.input.permitted_max_stint_length = Some(length);
+        self
+    }
+
+    pub fn pace_segment(mut self, segment: PaceSegment) -> Self {
+        self.input.pace_profile.push(segment);
+        self
+    }
+
+    pub fn fuel_weight_penalty(mut self, secs_per_unit: f64) -> Self {
+        self.input.fuel_weight_penalty = Some(secs_per_unit);
+        self
+    }
+
+    pub fn race_start(mut self, start: DateTime<Utc>) -> Self {
+        self.input.race_start = Some(start);
+        self
+    }
+
+    pub fn pit_stop_duration(mut self, duration: Duration) -> Self {
+        self.input.pit_stop_duration = Some(duration);
+        self
+    }
+
+    pub fn energy(mut self, capacity: f64, per_lap: f64) -> Self {
+        self.input.energy_capacity = Some(capacity);
+        self.input.energy_per_lap = Some(per_lap);
+        self
+    }
+
+    pub fn pit_window(mut self, window: PitWindow) -> Self {
+        self.input.pit_windows.push(window);
+        self
+    }
+
+    pub fn refuelling_banned(mut self) -> Self {
+        self.input.refuelling_banned = true;
+        self
+    }
+
+    pub fn wet_period(mut self, period: WetPeriod) -> Self {
+        self.input.forecast.push(period);
+        self
+    }
+
+    pub fn wet_laptime(mut self, laptime: Duration) -> Self {
+        self.input.wet_laptime = Some(laptime);
+        self
+    }
+
+    pub fn fuel_units(mut self, units: FuelUnits) -> Self {
+        self.input.fuel_units = units;
+        self
+    }
+
+    pub fn build(self) -> Result<StrategyInput, StrategyError> {
+        self.input.validate()?;
+        Ok(self.input)
+    }
+}
+
+impl StrategyInner {
+    /// Fuel to start the race with, which is everything the race needs that isn't added at a stop
+    pub fn starting_fuel(&self) -> f64 {
+        let required: f64 = self.stints.iter().map(|stint| stint.fuel_required).sum();
+        let added: f64 = self.stops.iter().map(|stop| stop.fuel_to_add).sum();
+        required - added
+    }
+
+    /// Total time lost to carrying fuel, for comparing strategies
+    pub fn fuel_weight_cost(&self) -> Duration {
+        self.stints.iter().map(|stint| stint.fuel_weight_cost).sum()
+    }
+
+    /// Lay the plan out as a plain text table, with one row for each stint and the stop ending it
+    pub fn as_table(&self) -> String {
+        let scheduled = self.stints.iter().any(|stint| stint.start_time.is_some());
+        let energy_limited = self
+            .stints
+            .iter()
+            .any(|stint| stint.energy_required.is_some());
+        let weather = self.stints.iter().any(|stint| stint.tyres.is_some());
+
+        let fuel = format!("Fuel {}", self.fuel_units.refuel_unit.symbol());
+        let add_fuel = format!("Add {}", self.fuel_units.refuel_unit.symbol());
+        let mut header = vec!["Stint", "Duration", "Laps", &fuel, "Stop Lap", &add_fuel];
+        if scheduled {
+            header.insert(1, "Start UTC");
+        }
+        if energy_limited {
+            header.extend(&["Energy", "Add Energy"]);
+        }
+        if weather {
+            header.push("Tyres");
+        }
+        let mut rows = vec![header.into_iter().map(String::from).collect::<Vec<_>>()];
+
+        for (i, stint) in self.stints.iter().enumerate() {
+            let stop = self.stops.get(i);
+            let mut row = vec![
+                (i + 1).to_string(),
+                format_hms(stint.duration),
+                stint.laps.to_string(),
+                self.fuel_units.format_amount(stint.fuel_required),
+                stop.map_or_else(|| "-".to_string(), |stop| stop.lap.to_string()),
+                stop.map_or_else(
+                    || "-".to_string(),
+                    |stop| self.fuel_units.format_amount(stop.fuel_to_add),
+                ),
+            ];
+            if scheduled {
+                let start = stint
+                    .start_time
+                    .map_or_else(String::new, |start| start.format("%H:%M").to_string());
+                row.insert(1, start);
+            }
+            if energy_limited {
+                row.push(
+                    stint
+                        .energy_required
+                        .map_or_else(String::new, format_energy),
+                );
+                row.push(
+                    stop.and_then(|stop| stop.energy_to_add)
+                        .map_or_else(|| "-".to_string(), format_energy),
+                );
+            }
+            if weather {
+                row.push(stint.tyres.map_or("", |tyres| tyres.name()).to_string());
+            }
+            rows.push(row);
+        }
+
+        let widths: Vec<usize> = (0..rows[0].len())
+            .map(|col| rows.iter().map(|row| row[col].len()).max().unwrap_or(0))
+            .collect();
+        rows.iter()
+            .map(|row| {
+                row.iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:>width$}", cell, width = width))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Format a duration as H:MM:SS, rounded to the nearest second
+pub fn format_hms(d: Duration) -> String {
+    let secs = round_secs(d).as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Format an amount of virtual energy, rounded up to a tenth so there's always enough
+pub fn format_energy(energy: f64) -> String {
+    format!("{:.1}", (energy * 10.0 - 1e-9).ceil() / 10.0)
+}
+
+fn to_chrono(d: Duration) -> chrono::Duration {
+    chrono::Duration::milliseconds(d.as_millis() as i64)
+}
+
+/// Round to the nearest second so durations read cleanly
+pub fn round_secs(d: Duration) -> Duration {
+    Duration::new(d.as_secs_f64().round() as u64, 0)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyInner {
+    pub stints: Vec<Stint>,
+    pub stops: Vec<Stop>,
+    /// Units of the fuel amounts above, for showing them as the refuel menu wants
+    pub fuel_units: FuelUnits,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyInput {
+    #[serde(with = "secs")]
+    pub race_duration: Duration,
+    #[serde(with = "secs")]
+    pub avg_laptime: Duration,
+    pub fuel_per_lap: f64,
+    pub fuel_capacity: f64,
+    pub mandatory_pits: Option<u8>,
+    #[serde(with = "secs::option")]
+    pub permitted_max_stint_length: Option<Duration>,
+    /// Changes of lap time and consumption through the race, replacing the averages above
+    pub pace_profile: Vec<PaceSegment>,
+    /// Seconds per lap added by each unit of fuel on board, on top of the lap time
+    pub fuel_weight_penalty: Option<f64>,
+    /// When the race starts, for scheduling stints and stops in real time
+    pub race_start: Option<DateTime<Utc>>,
+    /// Time spent in the pit lane for each stop, which pushes the rest of the schedule back
+    #[serde(with = "secs::option")]
+    pub pit_stop_duration: Option<Duration>,
+    /// Unit the fuel figures are in, and how refuelling amounts are shown
+    #[serde(default)]
+    pub fuel_units: FuelUnits,
+    /// Size of the virtual energy tank, 100 if only the energy per lap is given
+    pub energy_capacity: Option<f64>,
+    /// Virtual energy used each lap, for classes limited by energy as well as fuel
+    pub energy_per_lap: Option<f64>,
+    /// Periods of the race in which a stop must be made, one stop in each
+    #[serde(default)]
+    pub pit_windows: Vec<PitWindow>,
+    /// Whether the car must start with enough fuel for the whole race
+    #[serde(default)]
+    pub refuelling_banned: bool,
+    /// Periods of the race the track is forecast to be wet, for planning changes of tyres
+    #[serde(default)]
+    pub forecast: Vec<WetPeriod>,
+    /// Lap time on wets in the wet
+    #[serde(default, with = "secs::option")]
+    pub wet_laptime: Option<Duration>,
+    /// Lap time on slicks in the wet, if known better than a guess from the time on wets
+    #[serde(default, with = "secs::option")]
+    pub slicks_wet_laptime: Option<Duration>,
+    /// Lap time on wets in the dry, if known better than a guess from the average lap time
+    #[serde(default, with = "secs::option")]
+    pub wets_dry_laptime: Option<Duration>,
+}
+
+/// A period of race time in which the regulations require a stop
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PitWindow {
+    #[serde(with = "secs")]
+    pub open: Duration,
+    #[serde(with = "secs")]
+    pub close: Duration,
+}
+
+/// Lap time and consumption which apply from a given point in the race onwards
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaceSegment {
+    pub start: SegmentStart,
+    #[serde(with = "secs")]
+    pub laptime: Duration,
+    pub fuel_per_lap: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SegmentStart {
+    /// Elapsed race time
+    RaceTime(#[serde(with = "secs")] Duration),
+    /// Index of the stint, counting from zero
+    Stint(usize),
+}
+
+/// The lap time and consumption in force for a single stint
+#[derive(Debug, Clone, Copy)]
+struct Pace {
+    laptime: Duration,
+    fuel_per_lap: f64,
+    /// Seconds per lap added by each unit of fuel on board
+    weight_penalty: f64,
+    energy_per_lap: Option<f64>,
+}
+
+impl Pace {
+    /// Time lost carrying fuel over a stint which starts with just enough to complete it
+    fn weight_cost(&self, laps: u32) -> Duration {
+        Duration::from_secs_f64(self.weight_cost_secs(laps))
+    }
+
+    fn weight_cost_secs(&self, laps: u32) -> f64 {
+        let laps = laps as f64;
+        self.weight_penalty * self.fuel_per_lap * laps * (laps + 1.0) / 2.0
+    }
+
+    fn duration(&self, laps: u32) -> Duration {
+        laps * self.laptime + self.weight_cost(laps)
+    }
+
+    /// How long `laps` take, or None if that's too long to count
+    fn checked_duration(&self, laps: u32) -> Option<Duration> {
+        let weight_cost = Duration::try_from_secs_f64(self.weight_cost_secs(laps)).ok()?;
+        self.laptime.checked_mul(laps)?.checked_add(weight_cost)
+    }
+
+    /// How many laps it takes to fill `length`
+    fn laps(&self, length: Duration) -> u32 {
+        let mut laps = (length.as_secs_f64() / self.laptime.as_secs_f64()).ceil() as u32;
+        // Carrying fuel slows every lap, so we may get fewer laps in than an empty car would
+        while laps > 0 && self.duration(laps - 1) >= length {
+            laps -= 1;
+        }
+        laps
+    }
+
+    /// How many whole laps can be completed within `length`
+    fn laps_within(&self, length: Duration) -> u32 {
+        let laps = self.laps(length);
+        if self.duration(laps) > length {
+            laps - 1
+        } else {
+            laps
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stint {
+    #[serde(with = "secs")]
+    pub duration: Duration,
+    pub laps: u32,
+    pub fuel_required: f64,
+    /// Virtual energy the stint uses, if energy is limited
+    pub energy_required: Option<f64>,
+    /// Time lost over the stint to the weight of the fuel on board
+    #[serde(with = "secs")]
+    pub fuel_weight_cost: Duration,
+    /// Wall-clock time the stint starts, if we know when the race does
+    pub start_time: Option<DateTime<Utc>>,
+    /// Tyres the stint is run on, if the plan allows for the weather
+    #[serde(default)]
+    pub tyres: Option<Tyres>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stop {
+    pub lap: u32,
+    pub fuel_to_add: f64,
+    /// Virtual energy to replenish, if energy is limited
+    pub energy_to_add: Option<f64>,
+    /// Wall-clock time the car comes in, if we know when the race starts
+    pub time: Option<DateTime<Utc>>,
+    /// Tyres fitted at the stop, if the plan allows for the weather
+    #[serde(default)]
+    pub tyres: Option<Tyres>,
+}
+
+impl StrategyInput {
+    fn base_pace(&self) -> Pace {
+        Pace {
+            laptime: self.avg_laptime,
+            fuel_per_lap: self.fuel_per_lap,
+            weight_penalty: self.fuel_weight_penalty.unwrap_or(0.0),
+            energy_per_lap: self.energy_per_lap,
+        }
+    }
+
+    /// The pace for a stint starting at `elapsed` race time. Where several segments apply the
+    /// last one listed wins, and the pace at the start of a stint is used for all of it.
+    fn pace_at(&self, stint: usize, elapsed: Duration) -> Pace {
+        self.pace_profile
+            .iter()
+            .rev()
+            .find(|segment| match segment.start {
+                SegmentStart::RaceTime(start) => start <= elapsed,
+                SegmentStart::Stint(start) => start <= stint,
+            })
+            .map(|segment| self.pace_at_segment(segment))
+            .unwrap_or_else(|| self.base_pace())
+    }
+
+    fn pace_at_segment(&self, segment: &PaceSegment) -> Pace {
+        Pace {
+            laptime: segment.laptime,
+            fuel_per_lap: segment.fuel_per_lap,
+            ..self.base_pace()
+        }
+    }
+
+    fn fuel_duration(&self, pace: &Pace, fuel: f64) -> Duration {
+        // Intentionally truncate the laps value here by discarding the fractional part
+        let laps = (fuel / pace.fuel_per_lap) as u32;
+        pace.duration(laps)
+    }
+
+    fn energy_capacity(&self) -> f64 {
+        self.energy_capacity.unwrap_or(DEFAULT_ENERGY_CAPACITY)
+    }
+
+    /// How long a full tank lasts, which is until either the fuel or the energy runs out
+    fn max_fuel_duration(&self, pace: &Pace) -> Duration {
+        let fuel_duration = self.fuel_duration(pace, self.fuel_capacity);
+        match pace.energy_per_lap {
+            Some(energy_per_lap) => {
+                let laps = (self.energy_capacity() / energy_per_lap) as u32;
+                std::cmp::min(fuel_duration, pace.duration(laps))
+            }
+            None => fuel_duration,
+        }
+    }
+
+    fn stint(&self, pace: &Pace, laps: u32, duration: Duration) -> Stint {
+        Stint {
+            duration,
+            laps,
+            fuel_required: laps as f64 * pace.fuel_per_lap,
+            energy_required: pace.energy_per_lap.map(|energy| laps as f64 * energy),
+            fuel_weight_cost: pace.weight_cost(laps),
+            start_time: None,
+            tyres: None,
+        }
+    }
+
+    /// The longest possible stint duration based on regulations, fuel and energy capacity
+    fn max_stint_time(&self, pace: &Pace) -> Duration {
+        if let Some(stint_time) = self.permitted_max_stint_length {
+            std::cmp::min(stint_time, self.max_fuel_duration(pace))
+        } else {
+            self.max_fuel_duration(pace)
+        }
+    }
+
+    /// How many laps it takes to cover the race duration at the average lap time
+    pub(crate) fn race_laps(&self) -> u32 {
+        self.base_pace().laps(self.race_duration)
+    }
+
+    /// The most laps that can be run between stops at the average pace, based on regulations
+    /// and fuel capacity
+    pub(crate) fn max_stint_laps(&self) -> u32 {
+        let pace = self.base_pace();
+        pace.laps_within(self.max_stint_time(&pace))
+    }
+
+    /// How many stints are required based only on fuel consumption and capacity
+    fn fuel_required_stints(&self) -> u8 {
+        let stints = self.calculate_stints(|pace| self.max_fuel_duration(pace));
+        u8::try_from(stints.len()).unwrap_or(u8::MAX)
+    }
+
+    /// How many stints are required given the number of mandatory pits and pit windows in the
+    /// input
+    fn mandatory_pits_required_stints(&self) -> u8 {
+        let required = self.mandatory_pits.unwrap_or(0);
+        let windows = u8::try_from(self.pit_windows.len()).unwrap_or(u8::MAX);
+        std::cmp::max(required, windows).saturating_add(1)
+    }
+
+    /// How many stints are required given the maximum permitted stint length in the input
+    fn permitted_stint_length_required_stints(&self) -> u8 {
+        if let Some(max) = self.permitted_max_stint_length {
+            let stints = (self.race_duration.as_secs_f64() / max.as_secs_f64()).ceil() as u64;
+            u8::try_from(stints).unwrap_or(u8::MAX)
+        } else {
+            1
+        }
+    }
+
+    /// How many stints are required, taking into account fuel and regulations
+    pub(crate) fn required_stints(&self) -> u8 {
+        std::cmp::max(
+            std::cmp::max(
+                self.fuel_required_stints(),
+                self.mandatory_pits_required_stints(),
+            ),
+            self.permitted_stint_length_required_stints(),
+        )
+    }
+
+    // If we require more stints than the car's endurance allows, these must be mandatory
+    // stops which are assumed to involve a tyre change. In these circumstances, running long
+    // stints is of no advantage so we should show only the even-stints model
+    fn all_pits_mandatory(&self) -> bool {
+        let time_required_stints = self
+            .calculate_stints(|pace| self.max_stint_time(pace))
+            .len() as u32;
+
+        if let Some(mandatory) = self.mandatory_pits {
+            (time_required_stints - 1) <= mandatory as u32
+        } else {
+            false
+        }
+    }
+
+    /// Whether every stint can be run on a tank of fuel and within the permitted stint length
+    pub(crate) fn stints_within_limits(&self, stints: &[Stint]) -> bool {
+        stints.iter().all(|stint| {
+            stint.fuel_required <= self.fuel_capacity
+                && stint
+                    .energy_required
+                    .is_none_or(|energy| energy <= self.energy_capacity())
+                && self
+                    .permitted_max_stint_length
+                    .is_none_or(|max| stint.duration <= max)
+        })
+    }
+
+    fn calculate_stints<F: Fn(&Pace) -> Duration>(&self, target_stint_time: F) -> Vec<Stint> {
+        let mut remaining_race_time = self.race_duration;
+
+        let mut stints = vec![];
+        while remaining_race_time.as_secs() != 0 {
+            let pace = self.pace_at(stints.len(), self.race_duration - remaining_race_time);
+            let this_stint_time = std::cmp::min(remaining_race_time, target_stint_time(&pace));
+            if this_stint_time.as_secs() == 0 {
+                // Not even a single lap is possible at this pace, so give up rather than loop
+                break;
+            }
+
+            stints.push(self.stint(&pace, pace.laps(this_stint_time), this_stint_time));
+
+            // This can panic if there's an overflowing subtraction, so just zero it if that would occur
+            remaining_race_time = if this_stint_time >= remaining_race_time {
+                Duration::new(0, 0)
+            } else {
+                remaining_race_time - this_stint_time
+            };
+        }
+
+        stints
+    }
+
+    /// Build a plan which stops on the given laps, rather than wherever the calculator would
+    pub(crate) fn plan_with_stops(&self, stop_laps: &[u32]) -> StrategyInner {
+        let mut stints = vec![];
+        let mut elapsed = Duration::new(0, 0);
+        let mut last_lap = 0;
+        for (i, &lap) in stop_laps.iter().enumerate() {
+            let pace = self.pace_at(i, elapsed);
+            let laps = lap.saturating_sub(last_lap);
+            let duration = pace.duration(laps);
+            stints.push(self.stint(&pace, laps, duration));
+            elapsed += duration;
+            last_lap = lap;
+        }
+
+        // The final stint runs to the flag
+        let pace = self.pace_at(stop_laps.len(), elapsed);
+        let remaining = self.race_duration.checked_sub(elapsed).unwrap_or_default();
+        stints.push(self.stint(&pace, pace.laps(remaining), remaining));
+
+        let stops = self.calculate_stops(&stints);
+        self.schedule(stints, stops)
+    }
+
+    /// Build a plan from stints whose laps and durations have already been worked out, using the
+    /// average consumption
+    pub(crate) fn plan_with_stints(&self, stints: &[(u32, Duration)]) -> StrategyInner {
+        let pace = self.base_pace();
+        let stints: Vec<Stint> = stints
+            .iter()
+            .map(|&(laps, duration)| self.stint(&pace, laps, duration))
+            .collect();
+        let stops = self.calculate_stops(&stints);
+        self.schedule(stints, stops)
+    }
+
+    fn calculate_stops(&self, stints: &[Stint]) -> Vec<Stop> {
+        let mut lap = 0;
+        stints
+            .windows(2)
+            .map(|pair| {
+                lap += pair[0].laps;
+                Stop {
+                    lap,
+                    fuel_to_add: if self.refuelling_banned {
+                        0.0
+                    } else {
+                        pair[1].fuel_required
+                    },
+                    energy_to_add: pair[1].energy_required,
+                    time: None,
+                    tyres: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Fill in the wall-clock time of each stint and stop, allowing for time spent in the pits.
+    /// Any that would fall past the last time we can represent are left without one.
+    fn schedule(&self, mut stints: Vec<Stint>, mut stops: Vec<Stop>) -> StrategyInner {
+        if let Some(race_start) = self.race_start {
+            let pit_stop_duration = self
+                .pit_stop_duration
+                .unwrap_or_else(|| Duration::new(0, 0));
+            let mut elapsed = Duration::new(0, 0);
+            for (i, stint) in stints.iter_mut().enumerate() {
+                stint.start_time = race_start.checked_add_signed(to_chrono(elapsed));
+                elapsed += stint.duration;
+
+                if let Some(stop) = stops.get_mut(i) {
+                    stop.time = race_start.checked_add_signed(to_chrono(elapsed));
+                    elapsed += pit_stop_duration;
+                }
+            }
+        }
+
+        StrategyInner {
+            stints,
+            stops,
+            fuel_units: self.fuel_units,
+        }
+    }
+
+    fn calculate_even_stint_strategy(&self) -> Strategy {
+        let mut required_stints = self.required_stints();
+        let stints = loop {
+            let target_stint_time = Duration::from_secs_f64(
+                (self.race_duration.as_secs_f64() / required_stints as f64).ceil(),
+            );
+            let stints = self.calculate_stints(|_| target_stint_time);
+
+            // A slower or thirstier part of the race can leave an even split short of fuel,
+            // in which case we need another stint to share the load
+            if self.stints_within_limits(&stints) || required_stints == u8::MAX {
+                break stints;
+            }
+            required_stints += 1;
+        };
+        let stops = self.calculate_stops(&stints);
+
+        Strategy::EqualStints(self.schedule(stints, stops))
+    }
+
+    fn calculate_long_stint_strategy(&self) -> Strategy {
+        let stints = self.calculate_stints(|pace| self.max_stint_time(pace));
+        let stops = self.calculate_stops(&stints);
+
+        Strategy::LongStints(self.schedule(stints, stops))
+    }
+
+    fn calculate_single_stint(&self) -> Strategy {
+        let stints = self.calculate_stints(|pace| self.max_stint_time(pace));
+
+        Strategy::SingleStint(self.schedule(stints, vec![]))
+    }
+
+    /// Stop as late as possible in each pit window, stopping early to get there if a tank won't
+    /// last, then share out the rest of the race between any other stints that are needed
+    fn calculate_pit_window_strategy(&self) -> Strategy {
+        let mut stops: Vec<u32> = vec![];
+        // Lap and race time at the start of the current stint
+        let mut lap = 0;
+        let mut elapsed = Duration::new(0, 0);
+        let pit_stop_duration = self.pit_stop_duration.unwrap_or_default();
+
+        let mut windows = self.pit_windows.iter().peekable();
+        while let Some(window) = windows.peek() {
+            let pace = self.pace_at(stops.len(), elapsed);
+            let max_laps = pace.laps_within(self.max_stint_time(&pace)).max(1);
+            let close_laps = pace.laps_within(window.close.saturating_sub(elapsed));
+            let open_laps = pace.laps(window.open.saturating_sub(elapsed));
+
+            let laps = if close_laps <= max_laps {
+                windows.next();
+                close_laps.max(1)
+            } else {
+                if open_laps <= max_laps {
+                    windows.next();
+                }
+                // Either the latest we can stop within the window, or on the way to it
+                max_laps
+            };
+
+            lap += laps;
+            elapsed += pace.duration(laps) + pit_stop_duration;
+            stops.push(lap);
+        }
+
+        // Split whatever is left evenly between as many stints as fuel and regulations need
+        let remaining_laps = self
+            .plan_with_stops(&stops)
+            .stints
+            .last()
+            .map_or(0, |s| s.laps);
+        let pace = self.pace_at(stops.len(), elapsed);
+        let max_laps = pace.laps_within(self.max_stint_time(&pace)).max(1);
+        let mandatory = self.mandatory_pits.unwrap_or(0) as u32;
+        let stints = std::cmp::max(
+            (remaining_laps as f64 / max_laps as f64).ceil() as u32,
+            (mandatory + 1).saturating_sub(stops.len() as u32),
+        )
+        .max(1);
+        for i in 1..stints {
+            stops.push(lap + remaining_laps * i / stints);
+        }
+
+        Strategy::PitWindows(self.plan_with_stops(&stops))
+    }
+
+    pub fn calculate(&self) -> Vec<Strategy> {
+        // Rain decides when to stop as much as fuel does
+        if !self.forecast.is_empty() {
+            return vec![self.calculate_weather_strategy()];
+        }
+
+        // Regulated pit windows leave little choice of when to stop
+        if !self.pit_windows.is_empty() {
+            return vec![self.calculate_pit_window_strategy()];
+        }
+
+        // If a single stint is possible, return that alone
+        if self.required_stints() == 1 {
+            return vec![self.calculate_single_stint()];
+        }
+
+        let mut result = vec![];
+        if !self.all_pits_mandatory() {
+            // Running long stints is worthwhile as the last pitstops don't require a tyre change
+            result.push(self.calculate_long_stint_strategy());
+        }
+        result.push(self.calculate_even_stint_strategy());
+        result
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_fuel_req_only() {
+        let mut input = StrategyInput::builder(
+            Duration::new(7200, 0), // 2 hrs
+            Duration::new(138, 0),  // 2:18
+            3.93,
+            110.0,
+        )
+        .build()
+        .unwrap();
+
+        assert_eq!(2, input.required_stints());
+
+        input.race_duration = Duration::new(600, 0); // 10m
+        assert_eq!(1, input.required_stints());
+    }
+
+    #[test]
+    fn test_stints_mandatory_pit() {
+        let mut input = StrategyInput::builder(
+            Duration::new(7200, 0), // 2 hrs
+            Duration::new(138, 0),  // 2:18
+            3.93,
+            110.0,
+        )
+        .mandatory_pits(2)
+        .build()
+        .unwrap();
+
+        assert_eq!(3, input.required_stints());
+
+        input.race_duration = Duration::new(600, 0); // 10m
+        assert_eq!(3, input.required_stints());
+    }
+
+    #[test]
+    fn test_stints_max_time() {
+        let input = StrategyInput::builder(
+            Duration::new(7200, 0), // 2 hrs
+            Duration::new(138, 0),  // 2:18
+            3.93,
+            125.0,
+        )
+        .max_stint_length(Duration::new(3540, 0)) // 55mins
+        .build()
+        .unwrap();
+
+        assert_eq!(3, input.required_stints());
+    }
+
+    #[test]
+    fn test_all_mandatory_pits() {
+        let mut input = StrategyInput::builder(
+            Duration::new(7200, 0), // 2 hrs
+            Duration::new(138, 0),  // 2:18
+            3.93,
+            125.0,
+        )
+        .max_stint_length(Duration::new(3540, 0)) // 55mins
+        .mandatory_pits(1)
+        .build()
+        .unwrap();
+        assert!(!input.all_pits_mandatory());
+
+        input.mandatory_pits = Some(2);
+        assert!(input.all_pits_mandatory());
+    }
+
+    #[test]
+    fn calculate_even_stints_strategy() {
+        let input = StrategyInput::builder(
+            Duration::new(8640, 0), // 2 hrs 24 mins
+            Duration::new(138, 0),  // 2:18
+            3.93,
+            125.0,
+        )
+        .max_stint_length(Duration::new(3540, 0)) // 55mins
+        .build()
+        .unwrap();
+
+        let result = input.calculate_even_stint_strategy();
+        dbg!(&result);
+        match result {
+            Strategy::EqualStints(strat) => {
+                assert_eq!(3, strat.stints.len());
+                assert_eq!(2, strat.stops.len());
+
+                assert_eq!(21, strat.stops[0].lap);
+            }
+            _ => panic!("Got wrong enum variant"),
+        }
+    }
+
+    #[test]
+    fn calculate_long_stint_strategy() {
+        let input = StrategyInput::builder(
+            Duration::new(14400, 0), // 4 hrs
+            Duration::new(138, 0),   // 2:18
+            3.25,
+            110.0,
+        )
+        .mandatory_pits(3)
+        .build()
+        .unwrap();
+
+        let result = input.calculate_long_stint_strategy();
+        match result {
+            Strategy::LongStints(strat) => {
+                assert_eq!(4, strat.stints.len());
+                assert_eq!(3, strat.stops.len());
+
+                assert_eq!(33, strat.stops[0].lap);
+                assert_eq!(
+                    108.0,
+                    strat.fuel_units.refuel_amount(strat.stops[0].fuel_to_add)
+                );
+            }
+            _ => panic!("Got wrong enum variant"),
+        }
+    }
+
+    #[test]
+    fn calculate_simple_strategy() {
+        let input = StrategyInput::builder(
+            Duration::new(3600, 0), // 1 hr
+            Duration::new(138, 0),  // 2:18
+            3.25,
+            110.0,
+        )
+        .build()
+        .unwrap();
+
+        let result = input.calculate();
+        assert_eq!(1, result.len());
+        match &result[0] {
+            Strategy::SingleStint(strat) => {
+                assert_eq!(
+                    strat
+                        .fuel_units
+                        .refuel_amount(strat.stints[0].fuel_required),
+                    88.0
+                );
+            }
+            _ => panic!("Got wrong type of strategy for single stint race"),
+        }
+    }
+
+    #[test]
+    fn calculates_both_strategies() {
+        let input = StrategyInput::builder(
+            Duration::new(14400, 0), // 4 hrs
+            Duration::new(138, 0),   // 2:18
+            3.90,
+            110.0,
+        )
+        .build()
+        .unwrap();
+
+        let result = input.calculate();
+        assert_eq!(2, result.len());
+    }
+
+    #[test]
+    fn calculates_only_one_strategy_where_appropriate() {
+        let input = StrategyInput::builder(
+            Duration::new(14400, 0), // 4 hrs
+            Duration::new(138, 0),   // 2:18
+            3.90,
+            110.0,
+        )
+        // All pitstops are tyre changes, so the long-stints strategy doesn't make sense
+        .mandatory_pits(3)
+        .build()
+        .unwrap();
+
+        let result = input.calculate();
+        assert_eq!(1, result.len());
+    }
+
+    #[test]
+    fn night_pace_shortens_stints() {
+        let input = StrategyInput::builder(
+            Duration::new(14400, 0), // 4 hrs
+            Duration::new(138, 0),   // 2:18
+            3.25,
+            110.0,
+        )
+        .pace_segment(PaceSegment {
+            start: SegmentStart::RaceTime(Duration::new(7200, 0)), // Dark after 2 hrs
+            laptime: Duration::new(144, 0),                        // 2:24
+            fuel_per_lap: 3.6,
+        })
+        .build()
+        .unwrap();
+
+        match input.calculate_long_stint_strategy() {
+            Strategy::LongStints(strat) => {
+                assert_eq!(4, strat.stints.len());
+                assert_eq!(33, strat.stints[1].laps);
+                assert_eq!(30, strat.stints[2].laps);
+                assert_eq!(Duration::new(4320, 0), strat.stints[2].duration);
+
+                assert_eq!(66, strat.stops[1].lap);
+                assert_eq!(
+                    108.0,
+                    strat.fuel_units.refuel_amount(strat.stops[1].fuel_to_add)
+                );
+            }
+            _ => panic!("Got wrong enum variant"),
+        }
+    }
+
+    #[test]
+    fn even_stints_respect_thirsty_segments() {
+        let input = StrategyInput::builder(
+            Duration::new(7200, 0), // 2 hrs
+            Duration::new(120, 0),  // 2:00
+            2.0,
+            100.0,
+        )
+        .pace_segment(PaceSegment {
+            start: SegmentStart::RaceTime(Duration::new(3600, 0)),
+            laptime: Duration::new(120, 0),
+            fuel_per_lap: 4.0,
+        })
+        .build()
+        .unwrap();
+        assert_eq!(2, input.required_stints());
+
+        // Two equal stints would need 120 L for the second, so a third is added
+        match input.calculate_even_stint_strategy() {
+            Strategy::EqualStints(strat) => {
+                assert_eq!(3, strat.stints.len());
+                assert_eq!(
+                    80.0,
+                    strat
+                        .fuel_units
+                        .refuel_amount(strat.stints[2].fuel_required)
+                );
+            }
+            _ => panic!("Got wrong enum variant"),
+        }
+    }
+
+    #[test]
+    fn fuel_weight_slows_full_tanks() {
+        let input = StrategyInput::builder(
+            Duration::new(14400, 0), // 4 hrs
+            Duration::new(138, 0),   // 2:18
+            3.25,
+            110.0,
+        )
+        .fuel_weight_penalty(0.03)
+        .build()
+        .unwrap();
+
+        let long = input.calculate_long_stint_strategy();
+        let long = long.inner();
+        // 33 laps, with a full tank costing 0.03s per litre
+        assert_eq!(33, long.stints[0].laps);
+        assert_eq!(
+            Duration::from_secs_f64(33.0 * 138.0 + 0.03 * 3.25 * 33.0 * 34.0 / 2.0),
+            long.stints[0].duration
+        );
+
+        let even = input.calculate_even_stint_strategy();
+        assert!(long.fuel_weight_cost() > even.inner().fuel_weight_cost());
+    }
+
+    #[test]
+    fn schedules_stints_in_real_time() {
+        let input = StrategyInput::builder(
+            Duration::new(14400, 0), // 4 hrs
+            Duration::new(138, 0),   // 2:18
+            3.25,
+            110.0,
+        )
+        .mandatory_pits(3)
+        .race_start(Utc.ymd(2021, 5, 1).and_hms(18, 0, 0))
+        .pit_stop_duration(Duration::new(60, 0))
+        .build()
+        .unwrap();
+
+        let result = input.calculate_even_stint_strategy();
+        let strat = result.inner();
+        assert_eq!(input.race_start, strat.stints[0].start_time);
+        let late = StrategyInput {
+            race_start: Some(chrono::MAX_DATETIME),
+            ..input.clone()
+        };
+        let late = late.calculate_even_stint_strategy();
+        assert_eq!(
+            late.inner().stints[0].start_time,
+            Some(chrono::MAX_DATETIME)
+        );
+        assert!(late.inner().stops[0].time.is_none());
+        // Each stint is an hour, with a minute in the pits between them
+        assert_eq!(
+            Some(Utc.ymd(2021, 5, 1).and_hms(19, 0, 0)),
+            strat.stops[0].time
+        );
+        assert_eq!(
+            Some(Utc.ymd(2021, 5, 1).and_hms(19, 1, 0)),
+            strat.stints[1].start_time
+        );
+        assert_eq!(
+            Some(Utc.ymd(2021, 5, 1).and_hms(21, 3, 0)),
+            strat.stints[3].start_time
+        );
+    }
+
+    #[test]
+    fn builder_rejects_impossible_races() {
+        let laptime = Duration::new(138, 0);
+        let race = Duration::new(3600, 0);
+
+        assert!(StrategyInput::builder(race, laptime, 3.25, 110.0)
+            .mandatory_pits(1)
+            .build()
+            .is_ok());
+        assert!(StrategyInput::builder(race, laptime, 3.25, 3.0)
+            .build()
+            .is_err());
+        assert!(
+            StrategyInput::builder(race, Duration::new(0, 0), 3.25, 110.0)
+                .build()
+                .is_err()
+        );
+        assert!(StrategyInput::builder(race, laptime, 3.25, 110.0)
+            .pace_segment(PaceSegment {
+                start: SegmentStart::Stint(1),
+                laptime,
+                fuel_per_lap: 0.0,
+            })
+            .build()
+            .is_err());
+        for bad in [f64::NAN, f64::INFINITY] {
+            assert!(StrategyInput::builder(race, laptime, 3.25, bad)
+                .build()
+                .is_err());
+            assert!(StrategyInput::builder(race, laptime, bad, 110.0)
+                .build()
+                .is_err());
+            assert!(StrategyInput::builder(race, laptime, 3.25, 110.0)
+                .pace_segment(PaceSegment {
+                    start: SegmentStart::Stint(1),
+                    laptime,
+                    fuel_per_lap: bad,
+                })
+                .build()
+                .is_err());
+            assert!(StrategyInput::builder(race, laptime, 3.25, 110.0)
+                .energy(bad, 2.0)
+                .build()
+                .is_err());
+            assert!(StrategyInput::builder(race, laptime, 3.25, 110.0)
+                .energy(100.0, bad)
+                .build()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_weight_penalties_too_big_to_plan() {
+        let mut args = Arguments::new(vec!["1:00", "2:00", "2", "1e12", "weight=10"]);
+        assert!(StrategyInput::parse(&mut args).is_err());
+
+        let race = Duration::new(3600, 0);
+        let laptime = Duration::new(120, 0);
+        assert!(StrategyInput::builder(race, laptime, 2.0, 100.0)
+            .fuel_weight_penalty(f64::NAN)
+            .build()
+            .is_err());
+        assert!(StrategyInput::builder(race, laptime, 2.0, 100.0)
+            .fuel_weight_penalty(f64::INFINITY)
+            .build()
+            .is_err());
+        assert!(StrategyInput::builder(race, laptime, 2.0, 100.0)
+            .fuel_weight_penalty(10.0)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn formats_to_the_nearest_second() {
+        assert_eq!("1:02:03", format_hms(Duration::from_millis(3_723_400)));
+        assert_eq!("1:02:04", format_hms(Duration::from_millis(3_723_600)));
+    }
+
+    #[test]
+    fn stint_counts_saturate() {
+        let laptime = Duration::new(60, 0);
+        let race = Duration::new(24 * 3600, 0);
+
+        // 1440 laps on one lap of fuel a stint
+        let input = StrategyInput::builder(race, laptime, 1.0, 1.5)
+            .build()
+            .unwrap();
+        assert_eq!(u8::MAX, input.fuel_required_stints());
+
+        let input = StrategyInput::builder(race, laptime, 1.0, 110.0)
+            .mandatory_pits(u8::MAX)
+            .max_stint_length(Duration::new(60, 0))
+            .build()
+            .unwrap();
+        assert_eq!(u8::MAX, input.mandatory_pits_required_stints());
+        assert_eq!(u8::MAX, input.permitted_stint_length_required_stints());
+    }
+
+    #[test]
+    fn refuels_in_chosen_units() {
+        let mut args = Arguments::new(vec!["4:00", "2:18", "0.86", "29.1", "refuel_step=0.5"])
+            .with_defaults(vec![("unit".to_string(), "gal".to_string())]);
+        let input = StrategyInput::parse(&mut args).unwrap();
+        assert_eq!(FuelUnit::Gallons, input.fuel_units.unit);
+
+        let strats = input.calculate();
+        let strat = strats[0].inner();
+        // 33 laps at 0.86 gal is 28.38 gal, which the refuel menu can only take in half gallons
+        assert_eq!(33, strat.stints[1].laps);
+        assert_eq!(
+            "28.5 gal",
+            strat.fuel_units.format(strat.stops[0].fuel_to_add)
+        );
+
+        let mut args = Arguments::new(vec!["4:00", "2:18", "3.25", "110", "refuel_unit=kg"]);
+        let input = StrategyInput::parse(&mut args).unwrap();
+        let strats = input.calculate();
+        let strat = strats[0].inner();
+        // 107.25 L at 0.75 kg/L
+        assert_eq!("81 kg", strat.fuel_units.format(strat.stops[0].fuel_to_add));
+    }
+
+    #[test]
+    fn energy_limits_stints_before_fuel() {
+        let input = StrategyInput::builder(
+            Duration::new(14400, 0), // 4 hrs
+            Duration::new(138, 0),   // 2:18
+            3.25,
+            110.0,
+        )
+        .energy(100.0, 3.5)
+        .build()
+        .unwrap();
+
+        // Fuel alone would last 33 laps, but energy runs out after 28
+        let strats = input.calculate();
+        let strat = strats[0].inner();
+        assert_eq!(28, strat.stints[0].laps);
+        assert_eq!(28, strat.stops[0].lap);
+        assert_eq!("98.0", format_energy(strat.stops[0].energy_to_add.unwrap()));
+        assert!(input.stints_within_limits(&strat.stints));
+
+        assert!(StrategyInput::builder(
+            Duration::new(3600, 0), // 1 hr
+            Duration::new(138, 0),  // 2:18
+            3.25,
+            110.0,
+        )
+        .energy(100.0, 101.0)
+        .build()
+        .is_err());
+    }
+
+    #[test]
+    fn stops_within_pit_windows() {
+        let mut args = Arguments::new(vec![
+            "3:00",
+            "2:00",
+            "3",
+            "100",
+            "window=1:00-1:30",
+            "window=2:00-2:30",
+        ]);
+        let input = StrategyInput::parse(&mut args).unwrap();
+
+        let strats = input.calculate();
+        assert_eq!(1, strats.len());
+        assert_eq!("Pit Windows", strats[0].title());
+        // A tank lasts 33 laps, which reaches into each window but not to its close
+        let laps: Vec<u32> = strats[0]
+            .inner()
+            .stops
+            .iter()
+            .map(|stop| stop.lap)
+            .collect();
+        assert_eq!(vec![33, 66], laps);
+        assert!(input.stints_within_limits(&strats[0].inner().stints));
+    }
+
+    #[test]
+    fn no_fuel_added_when_refuelling_is_banned() {
+        let input = StrategyInput::builder(
+            Duration::new(3600, 0), // 1 hr
+            Duration::new(120, 0),  // 2:00
+            2.0,
+            70.0,
+        )
+        .mandatory_pits(1)
+        .refuelling_banned()
+        .build()
+        .unwrap();
+
+        let strats = input.calculate();
+        let strat = strats[0].inner();
+        assert_eq!(1, strat.stops.len());
+        assert_eq!(0.0, strat.stops[0].fuel_to_add);
+        assert_eq!(60.0, strat.starting_fuel());
+
+        assert!(StrategyInput::builder(
+            Duration::new(3600, 0), // 1 hr
+            Duration::new(120, 0),  // 2:00
+            2.0,
+            50.0,
+        )
+        .refuelling_banned()
+        .build()
+        .is_err());
+    }
+}
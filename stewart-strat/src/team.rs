@@ -0,0 +1,245 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::error::StrategyError;
+use crate::secs;
+use crate::strategy::{Strategy, StrategyInner, StrategyInput};
+
+/// Word separating the two cars' arguments, e.g. `6:00 2:18 3.4 110 vs 6:00 2:20 3.5 110`
+pub const SEPARATOR: &str = "vs";
+
+/// How many laps either way we'll move a stop to keep out of the other car's way
+const MAX_OFFSET: i64 = 5;
+
+/// How long a stop takes up the box when the input doesn't give a pit stop duration
+const DEFAULT_BOX_TIME: Duration = Duration::from_secs(30);
+
+/// Plans for two cars sharing a pit box, with the second car's stops moved around the first's
+#[derive(Debug, Clone, Serialize)]
+pub struct TeamStrategy {
+    pub cars: [Strategy; 2],
+    /// Stops of the second car which were moved off their planned lap
+    pub moved_stops: Vec<MovedStop>,
+    /// Stops where both cars still need the box at once
+    pub conflicts: Vec<Conflict>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MovedStop {
+    pub planned_lap: u32,
+    pub lap: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    /// The lap each car comes in on
+    pub laps: [u32; 2],
+    /// Time the later car spends waiting for the box
+    #[serde(with = "secs")]
+    pub cost: Duration,
+}
+
+impl TeamStrategy {
+    /// Total time lost waiting for the box
+    pub fn conflict_cost(&self) -> Duration {
+        self.conflicts.iter().map(|conflict| conflict.cost).sum()
+    }
+}
+
+/// A stop, in elapsed race time
+struct BoxVisit {
+    lap: u32,
+    arrives: Duration,
+    leaves: Duration,
+}
+
+/// Split arguments into those for each car at the [`SEPARATOR`]
+pub fn split(mut args: Vec<String>) -> Result<(Vec<String>, Vec<String>), StrategyError> {
+    let at = args
+        .iter()
+        .position(|arg| arg == SEPARATOR)
+        .ok_or(StrategyError::MissingArgument("second car"))?;
+    let second = args.split_off(at + 1);
+    args.pop();
+    Ok((args, second))
+}
+
+fn visits(input: &StrategyInput, plan: &StrategyInner) -> Vec<BoxVisit> {
+    let box_time = input.pit_stop_duration.unwrap_or(DEFAULT_BOX_TIME);
+    let mut elapsed = Duration::new(0, 0);
+    plan.stops
+        .iter()
+        .zip(&plan.stints)
+        .map(|(stop, stint)| {
+            elapsed += stint.duration;
+            let visit = BoxVisit {
+                lap: stop.lap,
+                arrives: elapsed,
+                leaves: elapsed + box_time,
+            };
+            elapsed += input.pit_stop_duration.unwrap_or_default();
+            visit
+        })
+        .collect()
+}
+
+/// How long the later of two visits waits for the box, if they overlap at all
+fn overlap(a: &BoxVisit, b: &BoxVisit) -> Option<Duration> {
+    let (earlier, later) = if a.arrives <= b.arrives {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    earlier
+        .leaves
+        .checked_sub(later.arrives)
+        .filter(|wait| wait.as_nanos() > 0)
+}
+
+/// Plan both cars, keeping the first car's plan and moving the second car's stops by a few laps
+/// where they'd otherwise need the box at the same time as the first car.
+///
+/// Stops are only moved where the new stints stay within fuel, energy and stint length limits, so
+/// any conflicts that can't be avoided that way are reported along with the time they cost.
+pub fn plan_team(first: &StrategyInput, second: &StrategyInput) -> TeamStrategy {
+    let lead = first.calculate().remove(0);
+    let mut other = second.calculate().remove(0);
+    let lead_visits = visits(first, lead.inner());
+
+    let clash = |plan: &StrategyInner, stop: usize| {
+        let visit = &visits(second, plan)[stop];
+        lead_visits
+            .iter()
+            .find_map(|lead| overlap(lead, visit).map(|cost| (lead.lap, cost)))
+    };
+
+    let mut plan = other.inner().clone();
+    let mut conflicts = vec![];
+    for stop in 0..plan.stops.len() {
+        let (lead_lap, cost) = match clash(&plan, stop) {
+            Some(clash) => clash,
+            None => continue,
+        };
+
+        let laps: Vec<u32> = plan.stops.iter().map(|stop| stop.lap).collect();
+        let earliest = if stop == 0 { 1 } else { laps[stop - 1] + 1 } as i64;
+        let race_laps = plan.stints.iter().map(|stint| stint.laps).sum::<u32>() as i64;
+        let last_stop = laps[laps.len() - 1] as i64;
+
+        // Later stops move along with this one so the stints after it keep their length. Coming
+        // in early is always safe for fuel, so try that before stretching a stint.
+        let alternative = (1..=MAX_OFFSET)
+            .flat_map(|offset| vec![-offset, offset])
+            .filter(|offset| {
+                laps[stop] as i64 + offset >= earliest && last_stop + offset < race_laps
+            })
+            .map(|offset| {
+                let moved: Vec<u32> = laps
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &lap)| {
+                        if i >= stop {
+                            (lap as i64 + offset) as u32
+                        } else {
+                            lap
+                        }
+                    })
+                    .collect();
+                second.plan_with_stops(&moved)
+            })
+            .find(|candidate| {
+                second.stints_within_limits(&candidate.stints) && clash(candidate, stop).is_none()
+            });
+
+        match alternative {
+            Some(alternative) => plan = alternative,
+            None => conflicts.push(Conflict {
+                laps: [lead_lap, laps[stop]],
+                cost,
+            }),
+        }
+    }
+
+    let moved_stops = other
+        .inner()
+        .stops
+        .iter()
+        .zip(&plan.stops)
+        .filter(|(planned, stop)| planned.lap != stop.lap)
+        .map(|(planned, stop)| MovedStop {
+            planned_lap: planned.lap,
+            lap: stop.lap,
+        })
+        .collect();
+    *other.inner_mut() = plan;
+
+    TeamStrategy {
+        cars: [lead, other],
+        moved_stops,
+        conflicts,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn moves_stops_away_from_the_other_car() {
+        let input = StrategyInput::builder(
+            Duration::new(14400, 0), // 4 hrs
+            Duration::new(138, 0),   // 2:18
+            3.25,
+            110.0,
+        )
+        .build()
+        .unwrap();
+
+        // Identical cars would stop together every time
+        let team = plan_team(&input, &input);
+        assert!(team.conflicts.is_empty());
+        assert_eq!(3, team.moved_stops.len());
+        for (lead, other) in team.cars[0]
+            .inner()
+            .stops
+            .iter()
+            .zip(&team.cars[1].inner().stops)
+        {
+            assert!(other.lap < lead.lap);
+        }
+        assert!(input.stints_within_limits(&team.cars[1].inner().stints));
+    }
+
+    #[test]
+    fn reports_conflicts_which_cant_be_avoided() {
+        // Two one hour stints are the only way to run this race, so both cars stop together
+        let input = StrategyInput::builder(
+            Duration::new(7200, 0), // 2 hrs
+            Duration::new(120, 0),  // 2:00
+            3.0,
+            110.0,
+        )
+        .mandatory_pits(1)
+        .max_stint_length(Duration::new(3600, 0))
+        .build()
+        .unwrap();
+
+        let team = plan_team(&input, &input);
+        assert!(team.moved_stops.is_empty());
+        assert_eq!(1, team.conflicts.len());
+        assert_eq!([30, 30], team.conflicts[0].laps);
+        assert_eq!(DEFAULT_BOX_TIME, team.conflict_cost());
+    }
+
+    #[test]
+    fn splits_arguments_for_each_car() {
+        let args = vec!["2:00", "2:18", "vs", "2:00", "2:20"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let (first, second) = split(args).unwrap();
+        assert_eq!(vec!["2:00", "2:18"], first);
+        assert_eq!(vec!["2:00", "2:20"], second);
+    }
+}
@@ -0,0 +1,152 @@
+//! Units of fuel. Calculations run in whatever unit consumption and capacity are given in, so
+//! units only matter when converting for the refuel menu and rounding to its increments.
+
+use serde::{Deserialize, Serialize};
+
+const LITRES_PER_GALLON: f64 = 3.785_411_784;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FuelUnit {
+    Litres,
+    /// US gallons
+    Gallons,
+    Kilograms,
+}
+
+impl FuelUnit {
+    /// Accept the short names and symbols people type in chat
+    pub fn parse(input: &str) -> Option<FuelUnit> {
+        match input.to_lowercase().as_str() {
+            "l" | "litre" | "litres" | "liter" | "liters" => Some(FuelUnit::Litres),
+            "gal" | "gallon" | "gallons" => Some(FuelUnit::Gallons),
+            "kg" | "kilogram" | "kilograms" => Some(FuelUnit::Kilograms),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            FuelUnit::Litres => "L",
+            FuelUnit::Gallons => "gal",
+            FuelUnit::Kilograms => "kg",
+        }
+    }
+
+    /// How many litres one of this unit is, given the fuel's density in kg per litre
+    fn litres(&self, density: f64) -> f64 {
+        match self {
+            FuelUnit::Litres => 1.0,
+            FuelUnit::Gallons => LITRES_PER_GALLON,
+            FuelUnit::Kilograms => 1.0 / density,
+        }
+    }
+}
+
+/// The unit fuel is given in, and how it should be shown when refuelling
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FuelUnits {
+    /// Unit of the fuel per lap and capacity given to the calculator
+    pub unit: FuelUnit,
+    /// Unit of the amounts shown for filling the car, usually the same as `unit`
+    pub refuel_unit: FuelUnit,
+    /// Kilograms per litre, for converting to or from kilograms
+    pub density: f64,
+    /// Smallest step the refuel menu allows, in `refuel_unit`
+    pub refuel_increment: f64,
+}
+
+impl Default for FuelUnits {
+    fn default() -> Self {
+        FuelUnits {
+            unit: FuelUnit::Litres,
+            refuel_unit: FuelUnit::Litres,
+            density: 0.75,
+            refuel_increment: 1.0,
+        }
+    }
+}
+
+impl FuelUnits {
+    /// Convert an amount of fuel from the input unit to the refuel unit
+    pub fn convert(&self, fuel: f64) -> f64 {
+        fuel * self.unit.litres(self.density) / self.refuel_unit.litres(self.density)
+    }
+
+    /// The amount to put in the car, rounded up to the refuel increment so it always covers `fuel`
+    pub fn refuel_amount(&self, fuel: f64) -> f64 {
+        let steps = self.convert(fuel) / self.refuel_increment;
+        // Allow for floating point error so exact multiples aren't bumped up a step
+        let steps = (steps - 1e-9).ceil();
+        if steps > 0.0 {
+            steps * self.refuel_increment
+        } else {
+            0.0
+        }
+    }
+
+    /// Format an amount to put in the car, e.g. "107.5 L"
+    pub fn format(&self, fuel: f64) -> String {
+        format!("{} {}", self.format_amount(fuel), self.refuel_unit.symbol())
+    }
+
+    /// As [`FuelUnits::format`] without the unit, for table cells
+    pub fn format_amount(&self, fuel: f64) -> String {
+        format!("{:.*}", self.decimals(), self.refuel_amount(fuel))
+    }
+
+    /// Decimal places needed to show multiples of the refuel increment
+    fn decimals(&self) -> usize {
+        (0..3)
+            .find(|&places| {
+                let scaled = self.refuel_increment * 10f64.powi(places as i32);
+                (scaled - scaled.round()).abs() < 1e-9
+            })
+            .unwrap_or(3)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        if !(self.density.is_finite() && self.density > 0.0) {
+            return Err("fuel density must be more than zero");
+        }
+        if !(self.refuel_increment.is_finite() && self.refuel_increment > 0.0) {
+            return Err("refuel step must be more than zero");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rounds_up_to_refuel_increment() {
+        let units = FuelUnits {
+            refuel_increment: 0.5,
+            ..FuelUnits::default()
+        };
+        assert_eq!(107.5, units.refuel_amount(107.25));
+        assert_eq!(108.0, units.refuel_amount(108.0));
+        assert_eq!("107.5 L", units.format(107.25));
+        assert_eq!("108 L", FuelUnits::default().format(107.25));
+    }
+
+    #[test]
+    fn converts_between_units() {
+        let units = FuelUnits {
+            unit: FuelUnit::Litres,
+            refuel_unit: FuelUnit::Kilograms,
+            density: 0.75,
+            refuel_increment: 0.1,
+        };
+        assert_eq!("75.0 kg", units.format(100.0));
+
+        let units = FuelUnits {
+            unit: FuelUnit::Gallons,
+            refuel_unit: FuelUnit::Litres,
+            ..FuelUnits::default()
+        };
+        assert_eq!("38 L", units.format(10.0));
+        assert_eq!(Some(FuelUnit::Gallons), FuelUnit::parse("gal"));
+    }
+}
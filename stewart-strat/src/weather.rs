@@ -0,0 +1,334 @@
+//! Planning around forecast rain: which tyres to start on, and on which lap to change between
+//! slicks and wets as the track gets wet or dries out.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::secs;
+use crate::strategy::{Strategy, StrategyInput};
+
+/// How much slower slicks are on a wet track than wets, if no lap time is given for them
+const SLICKS_IN_WET_FACTOR: f64 = 1.2;
+
+/// How much slower wets are on a dry track than slicks, if no lap time is given for them
+const WETS_IN_DRY_FACTOR: f64 = 1.08;
+
+/// Time lost to a stop if no pit time is given, which is what an extra stop for tyres costs
+const DEFAULT_PIT_LOSS: Duration = Duration::from_secs(60);
+
+/// How many laps either side of the change in conditions we consider changing tyres on
+const SEARCH_LAPS: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tyres {
+    Slicks,
+    Wets,
+}
+
+impl Tyres {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tyres::Slicks => "Slicks",
+            Tyres::Wets => "Wets",
+        }
+    }
+}
+
+/// A period of race time in which the track is forecast to be wet
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WetPeriod {
+    #[serde(with = "secs")]
+    pub start: Duration,
+    #[serde(with = "secs")]
+    pub end: Duration,
+}
+
+/// A stint of a plan run lap by lap
+struct RunStint {
+    laps: u32,
+    duration: Duration,
+    tyres: Tyres,
+}
+
+/// The result of running a plan lap by lap
+struct Run {
+    stints: Vec<RunStint>,
+    laps: u32,
+    /// Race time at the flag, including time in the pits
+    elapsed: Duration,
+}
+
+impl Run {
+    /// The laps on which the car changed compound, and what it changed to
+    fn changes(&self) -> Vec<(u32, Tyres)> {
+        let mut lap = 0;
+        self.stints
+            .windows(2)
+            .filter_map(|pair| {
+                lap += pair[0].laps;
+                (pair[0].tyres != pair[1].tyres).then_some((lap, pair[1].tyres))
+            })
+            .collect()
+    }
+}
+
+impl StrategyInput {
+    fn is_wet(&self, elapsed: Duration) -> bool {
+        self.forecast
+            .iter()
+            .any(|period| period.start <= elapsed && elapsed < period.end)
+    }
+
+    fn tyres_for(&self, elapsed: Duration) -> Tyres {
+        if self.is_wet(elapsed) {
+            Tyres::Wets
+        } else {
+            Tyres::Slicks
+        }
+    }
+
+    /// Lap time on the given tyres, in the given conditions
+    fn tyre_laptime(&self, tyres: Tyres, wet: bool) -> Duration {
+        let wet_laptime = self.wet_laptime.unwrap_or(self.avg_laptime);
+        match (tyres, wet) {
+            (Tyres::Slicks, false) => self.avg_laptime,
+            (Tyres::Wets, true) => wet_laptime,
+            (Tyres::Slicks, true) => self
+                .slicks_wet_laptime
+                .unwrap_or_else(|| wet_laptime.mul_f64(SLICKS_IN_WET_FACTOR)),
+            (Tyres::Wets, false) => self
+                .wets_dry_laptime
+                .unwrap_or_else(|| self.avg_laptime.mul_f64(WETS_IN_DRY_FACTOR)),
+        }
+    }
+
+    /// Run the race lap by lap, to `laps` laps or the flag if not given, asking `change` at the
+    /// end of each lap whether to come in for different tyres. Stops for fuel are made when the
+    /// tank runs out, keeping the same tyres.
+    fn run<F>(&self, laps: Option<u32>, change: F) -> Run
+    where
+        F: Fn(u32, Duration, Tyres) -> Option<Tyres>,
+    {
+        let max_laps = self.max_stint_laps().max(1);
+        let pit_loss = self.pit_stop_duration.unwrap_or(DEFAULT_PIT_LOSS);
+
+        let mut stints = vec![];
+        let mut tyres = self.tyres_for(Duration::new(0, 0));
+        let mut lap = 0;
+        let mut elapsed = Duration::new(0, 0);
+        let mut stint = RunStint {
+            laps: 0,
+            duration: Duration::new(0, 0),
+            tyres,
+        };
+        loop {
+            let laptime = self.tyre_laptime(tyres, self.is_wet(elapsed));
+            lap += 1;
+            elapsed += laptime;
+            stint.laps += 1;
+            stint.duration += laptime;
+
+            let finished = match laps {
+                Some(laps) => lap >= laps,
+                None => elapsed >= self.race_duration,
+            };
+            if finished {
+                break;
+            }
+
+            let change = change(lap, elapsed, tyres);
+            if change.is_some() || stint.laps >= max_laps {
+                tyres = change.unwrap_or(tyres);
+                stints.push(std::mem::replace(
+                    &mut stint,
+                    RunStint {
+                        laps: 0,
+                        duration: Duration::new(0, 0),
+                        tyres,
+                    },
+                ));
+                elapsed += pit_loss;
+            }
+        }
+        stints.push(stint);
+
+        Run {
+            stints,
+            laps: lap,
+            elapsed,
+        }
+    }
+
+    /// Run the race changing tyres on exactly the laps given
+    fn run_with_changes(&self, laps: Option<u32>, changes: &[(u32, Tyres)]) -> Run {
+        self.run(laps, |lap, _, tyres| {
+            changes
+                .iter()
+                .find(|&&(change_lap, _)| change_lap == lap)
+                .map(|&(_, change)| change)
+                .filter(|&change| change != tyres)
+        })
+    }
+
+    /// Start on the right tyres for the conditions, then work out the best lap to change on for
+    /// each change in the forecast, stopping for fuel whenever the tank runs out.
+    ///
+    /// Changes start on the lap the conditions change, then we drop any which don't pay for the
+    /// stop (a shower too short to be worth wets) and move the rest to whichever nearby lap loses
+    /// the least time over the race, which is often one where a stop for fuel is due anyway.
+    /// Consumption is the same on either compound and any pace profile is ignored.
+    pub(crate) fn calculate_weather_strategy(&self) -> Strategy {
+        let react = self.run(None, |_, elapsed, tyres| {
+            Some(self.tyres_for(elapsed)).filter(|&ideal| ideal != tyres)
+        });
+        let laps = react.laps;
+        let cost = |changes: &[(u32, Tyres)]| self.run_with_changes(Some(laps), changes).elapsed;
+
+        // Changes alternate between compounds, so dropping a pair keeps the rest of the plan
+        let mut changes = react.changes();
+        let mut i = 0;
+        while i < changes.len() {
+            let mut without = changes.clone();
+            without.drain(i..(i + 2).min(changes.len()));
+            if cost(&without) <= cost(&changes) {
+                changes = without;
+            } else {
+                i += 1;
+            }
+        }
+
+        for i in 0..changes.len() {
+            let earliest = if i == 0 { 1 } else { changes[i - 1].0 + 1 };
+            let latest = changes.get(i + 1).map_or(laps - 1, |next| next.0 - 1);
+            let planned = changes[i].0;
+            let mut best = (cost(&changes), planned);
+            for lap in planned.saturating_sub(SEARCH_LAPS).max(earliest)
+                ..=(planned + SEARCH_LAPS).min(latest)
+            {
+                changes[i].0 = lap;
+                let time = cost(&changes);
+                if time < best.0 {
+                    best = (time, lap);
+                }
+            }
+            changes[i].0 = best.1;
+        }
+
+        let mut run = self.run_with_changes(None, &changes);
+
+        // Make up any mandatory pits by splitting the longest stints, keeping the same tyres
+        let mandatory = self.mandatory_pits.unwrap_or(0) as usize;
+        let mut extra_stops: Vec<u32> = vec![];
+        while run.stints.len() <= mandatory {
+            let mut lap = 0;
+            let mut longest = (0, 0);
+            for stint in &run.stints {
+                if stint.laps > longest.1 {
+                    longest = (lap, stint.laps);
+                }
+                lap += stint.laps;
+            }
+            if longest.1 < 2 {
+                break;
+            }
+            extra_stops.push(longest.0 + longest.1 / 2);
+            run = self.run(None, |lap, _, tyres| {
+                changes
+                    .iter()
+                    .find(|&&(change_lap, _)| change_lap == lap)
+                    .map(|&(_, change)| change)
+                    .filter(|&change| change != tyres)
+                    .or_else(|| extra_stops.contains(&lap).then_some(tyres))
+            });
+        }
+
+        let stints: Vec<(u32, Duration)> = run
+            .stints
+            .iter()
+            .map(|stint| (stint.laps, stint.duration))
+            .collect();
+        let mut inner = self.plan_with_stints(&stints);
+        for (i, stint) in run.stints.iter().enumerate() {
+            inner.stints[i].tyres = Some(stint.tyres);
+            if i > 0 {
+                inner.stops[i - 1].tyres = Some(stint.tyres);
+            }
+        }
+
+        Strategy::Weather(inner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn input(forecast: Vec<WetPeriod>) -> StrategyInput {
+        let mut input = StrategyInput::builder(
+            Duration::new(3 * 3600, 0),
+            Duration::new(138, 0),
+            3.4,
+            120.0,
+        )
+        .pit_stop_duration(Duration::new(60, 0))
+        .build()
+        .unwrap();
+        input.forecast = forecast;
+        input.wet_laptime = Some(Duration::new(160, 0));
+        input.slicks_wet_laptime = Some(Duration::new(190, 0));
+        input
+    }
+
+    fn period(start_mins: u64, end_mins: u64) -> WetPeriod {
+        WetPeriod {
+            start: Duration::new(start_mins * 60, 0),
+            end: Duration::new(end_mins * 60, 0),
+        }
+    }
+
+    #[test]
+    fn changes_to_wets_and_back() {
+        let strategy = input(vec![period(60, 120)]).calculate_weather_strategy();
+        let inner = strategy.inner();
+        assert_eq!(Some(Tyres::Slicks), inner.stints[0].tyres);
+
+        let fitted: Vec<Tyres> = inner.stops.iter().filter_map(|stop| stop.tyres).collect();
+        let wets = fitted
+            .iter()
+            .position(|&tyres| tyres == Tyres::Wets)
+            .unwrap();
+        assert!(fitted[wets..].contains(&Tyres::Slicks));
+
+        // Nobody stays out on slicks for long once it's raining
+        let wet_lap = inner
+            .stops
+            .iter()
+            .find(|stop| stop.tyres == Some(Tyres::Wets));
+        let rain_lap = (3600 / 138) as u32;
+        assert!(wet_lap.unwrap().lap.abs_diff(rain_lap) <= SEARCH_LAPS);
+    }
+
+    #[test]
+    fn stays_out_through_a_short_shower() {
+        let strategy = input(vec![period(90, 93)]).calculate_weather_strategy();
+        let inner = strategy.inner();
+        assert!(inner
+            .stints
+            .iter()
+            .all(|stint| stint.tyres == Some(Tyres::Slicks)));
+        // The same stops for fuel as a dry race
+        assert_eq!(3, inner.stints.len());
+    }
+
+    #[test]
+    fn starts_on_wets_in_the_rain() {
+        let strategy = input(vec![period(0, 45)]).calculate_weather_strategy();
+        let inner = strategy.inner();
+        assert_eq!(Some(Tyres::Wets), inner.stints[0].tyres);
+        assert_eq!(Some(Tyres::Slicks), inner.stints.last().unwrap().tyres);
+        // Drying out needs a stop for slicks long before the tank runs dry, and no more after
+        // it than fuel requires
+        assert_eq!(2, inner.stops.len());
+    }
+}